@@ -11,7 +11,10 @@ mm:init(dummy_hash, '127.0.0.1', 3000, 1)
 if mm:check_config() == false then return end
 
 -- will join a private session by its secret
---mm:join_session("nssiaA1")
+--mm:join_session("nssiaA1", "secret")
+
+-- will join a public session matching our criteria
+--mm:join_session(nil, nil, { game_mode = "ffa", region = "eu" })
 
 -- will join any public session
 mm:join_session()
@@ -11,7 +11,9 @@ mm:init(dummy_hash, '127.0.0.1', 3000, 1)
 if mm:check_config() == false then return end
 
 -- create_session() creates a new session on the server
--- create_session(true) creates a private session
+-- create_session("secret") creates a private session locked by a password
+-- create_session(nil, 4) creates a session that starts once 4 players joined
+-- create_session(nil, 2, { name = "lobby", game_mode = "ffa", ranked = false }) attaches metadata
 mm:create_session()
 
 -- wait until we get our unique session key (secret)
@@ -1,6 +1,7 @@
 -- matchmaker lua module
 local socket = require("socket")
 local serializer = require("serializer")
+local sha256 = require("sha256")
 
 local lib = {
     ip = "",                   -- matchmaker server ip
@@ -9,7 +10,24 @@ local lib = {
     socket = nil,              -- udp socket
     session_key = "",          -- active session key (host only)
     remote_addr = "",          -- remote connection
-    client_hash = "",          -- crypto hash of client to verify authenticity
+    remote_local_addrs = {},   -- remote private addresses (same LAN candidates)
+    local_addr = "",           -- our private address reported to the server
+    punch_time = nil,          -- local time to start hole punching at
+    relay_channel = nil,       -- server relay channel when punching failed
+    relay_inbox = {},          -- datagrams recieved through the relay
+    reflexive_addr = "",       -- our address as seen by the server
+    roster = {},               -- public addresses of everyone in our session, host first
+    max_players = 2,           -- player count our session starts at
+    members = {},              -- everyone else once the session started { addr, local_addrs }
+    session_list = nil,        -- last page of the session browser { page, total, sessions }
+    protocol_version = nil,    -- version agreed on with the server, nil until welcomed
+    capabilities = 0,          -- capability bits agreed on with the server
+    pending_requests = {},     -- requests held back until the server authenticated us
+    authenticated = false,     -- true once the server accepted our challenge response
+    client_hash = "",          -- per build secret, proves authenticity without being sent
+    ticket = "",               -- signed ticket for reclaiming our state from a new address
+    restore = nil,             -- { session_key, token } for getting our session back after a server restart
+    handshake = nil,           -- first packet from this socket, repeated with the server's cookie
     sent_packets = {},         -- list of unack'd packaget that had been sent
     errors = {},               -- list of errors
     next_packet_id = 0,        -- our next packet ID
@@ -17,7 +35,24 @@ local lib = {
     max_packet_len = 512,      -- max packet len a socket can read
     debug = false,             -- Prints debug information to console
     is_joining = false,       -- indicates whether we were trying to join
-    join_status = ""           -- indicates if the last join failed
+    join_status = "",          -- indicates if the last join failed
+    server_closed = false      -- true once the server said it is shutting down
+}
+
+--[[
+Protocol version and capabilities we announce in Hello
+--]]
+local PROTOCOL_VERSION = 5
+
+--[[
+Packets opening a connection are padded so the server's retry reply is never larger
+--]]
+local MIN_HANDSHAKE_LEN = 64
+
+local Capability = {
+    Relay = 1,
+    EchoPort = 2,
+    Ranked = 4
 }
 
 --[[
@@ -30,7 +65,52 @@ local PacketHeader = {
     Create = 2,
     Join = 3 ,
     Close = 4,
-    Error = 5    
+    Error = 5,
+    Punch = 6,
+    Relay = 7,
+    RelayData = 8,
+    Echo = 9,
+    Roster = 10,
+    Start = 11,
+    List = 12,
+    Metadata = 13,
+    Hello = 14,
+    Auth = 15,
+    Reconnect = 16,
+    Retry = 17,
+    Restore = 18
+}
+
+--[[
+Error reasons are u16 size
+--]]
+local ErrorReason = {
+    SessionCreateFailed = 0,
+    InvalidPassword = 1,
+    RelayUnavailable = 2,
+    InvalidMetadata = 3,
+    UnsupportedVersion = 4,
+    HandshakeRequired = 5,
+    AuthenticationFailed = 6,
+    TicketRejected = 7,
+    RateLimited = 8,
+    TooManyAttempts = 9,
+    Banned = 10,
+    BuildBlocked = 11,
+    BuildDeprecated = 12,
+    IncompatibleBuild = 13,
+    ServerShutdown = 14,
+    PlayerIdRequired = 15,
+    RestoreRejected = 16
+}
+
+--[[
+Metadata values are tagged with a u8 type
+--]]
+local MetadataType = {
+    Bool = 0,
+    Int = 1,
+    Str = 2
 }
 
 --[[
@@ -38,9 +118,77 @@ Packet types are read differently
 --]]
 local PacketType = {
     AckPacket = 0,
-    DataPacket = 1
+    DataPacket = 1,
+    RelayPacket = 2,
+    EchoPacket = 3,
+    RetryPacket = 4
 }
 
+local function write_addresses(addrs, littleEndian)
+    serializer:write_u8(#addrs)
+
+    for _, addr in ipairs(addrs) do
+        serializer:write_string(addr, littleEndian)
+    end
+end
+
+-- metadata is a table of string keys to boolean, integer or string values
+local function write_metadata(metadata, littleEndian)
+    local keys = {}
+    for key, _ in pairs(metadata or {}) do
+        keys[#keys+1] = key
+    end
+
+    serializer:write_u8(#keys)
+
+    for _, key in ipairs(keys) do
+        local value = metadata[key]
+        serializer:write_string(key, littleEndian)
+
+        if type(value) == "boolean" then
+            serializer:write_u8(MetadataType.Bool)
+            serializer:write_u8(value and 1 or 0)
+        elseif type(value) == "number" then
+            serializer:write_u8(MetadataType.Int)
+            serializer:write_u32(math.floor(value), false, littleEndian)
+        else
+            serializer:write_u8(MetadataType.Str)
+            serializer:write_string(tostring(value), littleEndian)
+        end
+    end
+end
+
+local function read_metadata(littleEndian)
+    local metadata = {}
+    local count = serializer:read_u8()
+
+    for i = 1, count do
+        local key = serializer:read_string()
+        local value_type = serializer:read_u8()
+
+        if value_type == MetadataType.Bool then
+            metadata[key] = serializer:read_u8() == 1
+        elseif value_type == MetadataType.Int then
+            metadata[key] = serializer:read_u32(littleEndian)
+        else
+            metadata[key] = serializer:read_string()
+        end
+    end
+
+    return metadata
+end
+
+local function read_addresses()
+    local addrs = {}
+    local count = serializer:read_u8()
+
+    for i = 1, count do
+        addrs[i] = serializer:read_string()
+    end
+
+    return addrs
+end
+
 local function send_packet(ctx, packet_id, header, data)
     serializer:clear()
 
@@ -55,31 +203,80 @@ local function send_packet(ctx, packet_id, header, data)
         serializer:write_u32(data.id, false, littleEndian)
     end
 
-    -- { client_hash: str, password_protected: bool }
+    -- { password: str, password_after: u8, max_players: u8, metadata: metadata, local_addrs: [str] }
     if header == PacketHeader.Create then 
         ctx:_debug_print("Sending Create Packet")
 
-        serializer:write_string(data.client_hash, littleEndian)
-        
-        local value = 0
-        if data.password_protected then
-            value = 1
+        serializer:write_string(data.password or "", littleEndian)
+        serializer:write_u8(data.password_after or 0)
+        serializer:write_u8(data.max_players or 2)
+        write_metadata(data.metadata, littleEndian)
+        write_addresses(data.local_addrs, littleEndian)
+    end
+
+    -- { protocol_version: u16, capabilities: u32, cookie: bytes }
+    if header == PacketHeader.Hello then
+        ctx:_debug_print("Sending Hello Packet")
+
+        serializer:write_u16(data.protocol_version, false, littleEndian)
+        serializer:write_u32(data.capabilities, false, littleEndian)
+        serializer:write_string(data.cookie or "", littleEndian)
+    end
+
+    -- { mac: bytes }
+    if header == PacketHeader.Auth then
+        ctx:_debug_print("Sending Auth Packet")
+
+        serializer:write_string(data.mac, littleEndian)
+    end
+
+    -- { ticket: bytes, cookie: bytes }
+    if header == PacketHeader.Reconnect then
+        ctx:_debug_print("Sending Reconnect Packet")
+
+        serializer:write_string(data.ticket, littleEndian)
+        serializer:write_string(data.cookie or "", littleEndian)
+    end
+
+    -- { session_key: str, token: bytes }
+    if header == PacketHeader.Restore then
+        ctx:_debug_print("Sending Restore Packet")
+
+        serializer:write_string(data.session_key, littleEndian)
+        serializer:write_string(data.token, littleEndian)
+    end
+
+    if header == PacketHeader.Hello or header == PacketHeader.Reconnect then
+        ctx.handshake = { packet_id = packet_id, header = header, data = data }
+
+        while #serializer.Buffer < MIN_HANDSHAKE_LEN do
+            serializer:write_u8(0)
         end
+    end
 
-        serializer:write_u8(value)
+    -- { metadata: metadata }
+    if header == PacketHeader.Metadata then
+        ctx:_debug_print("Sending Metadata Packet")
+
+        write_metadata(data.metadata, littleEndian)
+    end
+
+    -- { filters: metadata, page: u16 }
+    if header == PacketHeader.List then
+        ctx:_debug_print("Sending List Packet")
+
+        write_metadata(data.filters, littleEndian)
+        serializer:write_u16(data.page or 0, false, littleEndian)
     end
 
-    -- { client_hash: str, session_key: str }
+    -- { session_key: str, password: str, criteria: metadata, local_addrs: [str] }
     if header == PacketHeader.Join then 
         ctx:_debug_print("Sending Join Packet")
 
-        serializer:write_string(data.client_hash, littleEndian)
-
-        if data.session_key ~= nil then
-            serializer:write_string(data.session_key, littleEndian)
-        else 
-            serializer:write_string("", littleEndian)
-        end
+        serializer:write_string(data.session_key or "", littleEndian)
+        serializer:write_string(data.password or "", littleEndian)
+        write_metadata(data.criteria, littleEndian)
+        write_addresses(data.local_addrs, littleEndian)
     end
 
     --[[
@@ -95,6 +292,16 @@ local function send_packet(ctx, packet_id, header, data)
     end
 end
 
+-- Session requests wait for the handshake, the server rejects them otherwise
+local function send_request(ctx, header, data)
+    if not ctx.authenticated then
+        ctx.pending_requests[#ctx.pending_requests+1] = { header = header, data = data }
+        return
+    end
+
+    send_packet(ctx, ctx.next_packet_id, header, data)
+end
+
 local function read_packet(ctx, bytestream)
     local littleEndian = serializer:endian() == "Little Endian"
 
@@ -103,6 +310,51 @@ local function read_packet(ctx, bytestream)
 
     serializer:set_buffer(bytestream)
 
+    -- { channel: u32, data: bytes }
+    if bytestream:byte(1) == PacketType.RelayPacket and #bytestream >= 5 then
+        serializer:read_u8()
+        local channel = serializer:read_u32(littleEndian)
+
+        if channel == ctx.relay_channel then
+            ctx.relay_inbox[#ctx.relay_inbox+1] = bytestream:sub(6)
+        end
+
+        return
+    end
+
+    -- { socket_address: str }
+    if bytestream:byte(1) == PacketType.EchoPacket then
+        serializer:read_u8()
+
+        if serializer:read_u16(littleEndian) == PacketHeader.Echo then
+            ctx.reflexive_addr = serializer:read_string()
+            ctx:_debug_print("Server sees us as "..ctx.reflexive_addr)
+        end
+
+        return
+    end
+
+    -- { cookie: bytes }
+    if bytestream:byte(1) == PacketType.RetryPacket then
+        serializer:read_u8()
+
+        local handshake = ctx.handshake
+
+        -- only the first retry counts, later ones are for packets we already repeated
+        if serializer:read_u16(littleEndian) == PacketHeader.Retry
+            and handshake ~= nil
+            and handshake.data.cookie == nil
+            and ctx.sent_packets[handshake.packet_id] ~= nil then
+            ctx:_debug_print("Retry packet recieved")
+
+            ctx.sent_packets[handshake.packet_id] = nil
+            handshake.data.cookie = serializer:read_string()
+            send_packet(ctx, ctx.next_packet_id, handshake.header, handshake.data)
+        end
+
+        return
+    end
+
     if #bytestream < 7 then
         ctx:_debug_print("Bytestream too small to interpret. Dropping")
         return
@@ -145,38 +397,180 @@ local function read_packet(ctx, bytestream)
     end
 
 
-    -- { id: u32, message: str }
+    -- { id: u32, reason: u16, message: str }
     if header == PacketHeader.Error then 
         local id = serializer:read_u32(littleEndian)
+        local reason = serializer:read_u16(littleEndian)
         local message = serializer:read_string()
         ctx:_debug_print("Error packet recieved: "..message)
         ctx.sent_packets[id] = nil
-        ctx.errors[#ctx.errors+1] = message
+        ctx.errors[#ctx.errors+1] = { reason = reason, message = message }
+
+        if ctx.is_joining and (reason == ErrorReason.InvalidPassword
+            or reason == ErrorReason.RateLimited
+            or reason == ErrorReason.TooManyAttempts
+            or reason == ErrorReason.IncompatibleBuild
+            or reason == ErrorReason.ServerShutdown
+            or reason == ErrorReason.PlayerIdRequired) then
+            ctx.join_status = "failed"
+            ctx.is_joining = false
+        end
+
+        -- the session expired or went to someone else, don't ask for it again
+        if reason == ErrorReason.RestoreRejected then
+            ctx.restore = nil
+        end
     end
 
-    -- { session_key: str }
+    -- {}
+    if header == PacketHeader.Close then
+        ctx:_debug_print("Close packet recieved")
+        ctx.server_closed = true
+        ctx.session_key = ""
+        ctx.roster = {}
+    end
+
+    -- { protocol_version: u16, capabilities: u32, nonce: bytes }
+    if header == PacketHeader.Hello then
+        ctx.protocol_version = serializer:read_u16(littleEndian)
+        ctx.capabilities = serializer:read_u32(littleEndian)
+        local nonce = serializer:read_string()
+        ctx:_debug_print("Welcome packet recieved, protocol version "..ctx.protocol_version)
+
+        -- prove we know the build secret without sending it
+        send_packet(ctx, ctx.next_packet_id, PacketHeader.Auth, { mac = sha256.hmac_sha256(ctx.client_hash, nonce) })
+    end
+
+    -- { success: bool }
+    if header == PacketHeader.Auth then
+        ctx:_debug_print("Auth packet recieved")
+        ctx.authenticated = serializer:read_u8() == 1
+
+        -- ack first so the server isn't left resending
+        send_packet(ctx, ctx.next_packet_id, PacketHeader.Ack, { id = packet_id })
+
+        local pending = ctx.pending_requests
+        ctx.pending_requests = {}
+
+        for _, request in ipairs(pending) do
+            send_request(ctx, request.header, request.data)
+        end
+
+        return
+    end
+
+    -- { session_key: str, ticket: bytes, restore_token: bytes }
+    -- also the reply to Restore, the token is replaced every time
     if header == PacketHeader.Create then 
         ctx:_debug_print("Create response packet recieved")
         local session_key = serializer:read_string()
         ctx.session_key = session_key
+        ctx.ticket = serializer:read_string()
+        ctx.restore = { session_key = session_key, token = serializer:read_string() }
+    end
+
+    -- { ticket: bytes }
+    if header == PacketHeader.Reconnect then
+        ctx:_debug_print("Reconnect packet recieved")
+        ctx.ticket = serializer:read_string()
     end
 
-    -- { success: bool, socket_address: str }
-    if header == PacketHeader.Join and ctx.is_joining then 
+    -- { success: bool, socket_address: str, local_addrs: [str], ticket: bytes }
+    if header == PacketHeader.Join then 
         ctx:_debug_print("Join response package recieved")
         local success = serializer:read_u8()
 
         if success == 1 then 
             local socket_address = serializer:read_string()
             ctx.remote_addr = socket_address
-            ctx.join_status = "success"
-        else 
+            ctx.remote_local_addrs = read_addresses()
+            ctx.ticket = serializer:read_string()
+
+            if ctx.is_joining then
+                ctx.join_status = "success"
+            end
+        elseif ctx.is_joining then
             ctx.join_status = "failed"
         end
 
         ctx.is_joining = false
     end
 
+    -- { session_key: str, max_players: u8, members: [str] }
+    if header == PacketHeader.Roster then
+        ctx:_debug_print("Roster packet recieved")
+        serializer:read_string()
+        ctx.max_players = serializer:read_u8()
+        ctx.roster = read_addresses()
+    end
+
+    -- { page: u16, total: u16, sessions: [{ session_key: str, age: u32, players: u8, max_players: u8, metadata: metadata }] }
+    if header == PacketHeader.List then
+        ctx:_debug_print("List packet recieved")
+        local list = {
+            page = serializer:read_u16(littleEndian),
+            total = serializer:read_u16(littleEndian),
+            sessions = {}
+        }
+
+        local count = serializer:read_u8()
+        for i = 1, count do
+            list.sessions[i] = {
+                session_key = serializer:read_string(),
+                age = serializer:read_u32(littleEndian),
+                players = serializer:read_u8(),
+                max_players = serializer:read_u8(),
+                metadata = read_metadata(littleEndian)
+            }
+        end
+
+        ctx.session_list = list
+    end
+
+    -- { members: [{ socket_address: str, local_addrs: [str] }] }
+    if header == PacketHeader.Start then
+        ctx:_debug_print("Start packet recieved")
+        ctx.members = {}
+
+        local count = serializer:read_u8()
+        for i = 1, count do
+            local addr = serializer:read_string()
+            ctx.members[i] = { addr = addr, local_addrs = read_addresses() }
+        end
+
+        -- 1v1 sessions only have a single remote
+        if count > 0 then
+            ctx.remote_addr = ctx.members[1].addr
+            ctx.remote_local_addrs = ctx.members[1].local_addrs
+        end
+
+        ctx.session_key = ""
+        ctx.restore = nil
+    end
+
+    -- { channel: u32, active: bool }
+    if header == PacketHeader.Relay then
+        ctx:_debug_print("Relay packet recieved")
+        local channel = serializer:read_u32(littleEndian)
+        local active = serializer:read_u8()
+
+        if active == 1 then
+            ctx.relay_channel = channel
+        elseif channel == ctx.relay_channel then
+            ctx.relay_channel = nil
+        end
+    end
+
+    -- { server_time: u64, start_time: u64 }
+    if header == PacketHeader.Punch then
+        ctx:_debug_print("Punch packet recieved")
+        local server_time = serializer:read_u64(littleEndian)
+        local start_time = serializer:read_u64(littleEndian)
+
+        -- our clock may not agree with the server, only trust the delay
+        ctx.punch_time = socket.gettime() + (start_time - server_time) / 1000
+    end
+
     -- send the ack packet to the server
     send_packet(ctx, ctx.next_packet_id, PacketHeader.Ack, { id = packet_id })
 end
@@ -189,6 +583,11 @@ function lib:get_join_status()
     return self.join_status
 end
 
+-- The server went away on purpose, the last error says when to try again
+function lib:is_server_closed()
+    return self.server_closed
+end
+
 function lib:check_config() 
     return string.len(self.ip) > 0 
     and self.port >= 1025 
@@ -196,18 +595,37 @@ function lib:check_config()
     and string.len(self.client_hash) > 0 
 end
 
+-- Call again after the server closed on us, a session we were hosting is
+-- asked back with the restore token the server gave us when it was created
 function lib:init(client_hash, ip, port, timeout, debug) 
     self.ip = ip
     self.port = port
     self.client_hash = client_hash
     self.session_key = ""
     self.remote_addr = ""
+    self.remote_local_addrs = {}
+    self.local_addr = ""
+    self.punch_time = nil
+    self.relay_channel = nil
+    self.relay_inbox = {}
+    self.reflexive_addr = ""
+    self.roster = {}
+    self.max_players = 2
+    self.members = {}
+    self.session_list = nil
+    self.protocol_version = nil
+    self.capabilities = 0
+    self.pending_requests = {}
+    self.authenticated = false
+    self.ticket = ""
+    self.handshake = nil
     self.sent_packets = {}
     self.errors = {}
     self.next_packet_id = 0
     self.server_next_packet_id = 0
     self.is_joining = false 
     self.join_status = "" 
+    self.server_closed = false
 
     if timeout ~= nil then
         self.timeout = timeout
@@ -228,13 +646,33 @@ function lib:init(client_hash, ip, port, timeout, debug)
         self.socket:setpeername(self.ip, self.port)
         self.socket:settimeout(self.timeout)
 
+        local local_ip, local_port = self.socket:getsockname()
+        if local_ip ~= nil and local_ip ~= "0.0.0.0" then
+            self.local_addr = local_ip..":"..local_port
+        end
+
         self.next_packet_id = 0
 
+        local hello = {
+            protocol_version = PROTOCOL_VERSION,
+            capabilities = Capability.Relay + Capability.EchoPort + Capability.Ranked
+        }
+
+        send_packet(self, self.next_packet_id, PacketHeader.Hello, hello)
+
+        -- the server restarted while we were hosting, ask for our session back
+        if self.restore ~= nil then
+            send_request(self, PacketHeader.Restore, self.restore)
+        end
+
         self:_debug_print("Host machine Endianess is "..serializer:endian())
     end
 end
 
-function lib:create_session(password_protected)
+-- With password_after set the password is only asked for once the session
+-- has seen that many failed joins, until then friends with the key get
+-- straight in. The count starts over once someone joins
+function lib:create_session(password, max_players, metadata, password_after)
     if self:check_config() then
         if self.is_joining then 
             self:_debug_print("You are in the middle of joining, request supressed")
@@ -243,18 +681,28 @@ function lib:create_session(password_protected)
 
         if string.len(self.session_key) == 0 then
             local data = {
-                client_hash = self.client_hash,
-                password_protected = password_protected
+                password = password,
+                password_after = password_after,
+                max_players = max_players,
+                metadata = metadata,
+                local_addrs = self:_local_addresses()
             }
 
-            send_packet(self, self.next_packet_id, PacketHeader.Create, data)
+            send_request(self, PacketHeader.Create, data)
         else 
             self:_debug_print("You have a session already @ "..self.session_key)
         end
     end
 end
 
-function lib:join_session(password)
+-- Without a session_key we are queued until a session matching our criteria
+-- opens, e.g. { game_mode = "ffa", region = "eu", rating = 1200 }.
+-- Region and rating requirements relax the longer we wait.
+-- Ranked play pairs us with another queued player instead of a host:
+-- { ranked = true, player_id = "abc", rating = 1200 }, the server remembers
+-- the last rating of a player id when it is left out. The join succeeds with
+-- the opponent as the remote, followed by the Start of the match.
+function lib:join_session(session_key, password, criteria)
     if self:check_config() then
         if self.is_joining then 
             self:_debug_print("You are in the middle of joining, request supressed")
@@ -263,10 +711,12 @@ function lib:join_session(password)
 
         if string.len(self.session_key) == 0 then
             local data = {
-                client_hash = self.client_hash,
-                session_key = password
+                session_key = session_key,
+                password = password,
+                criteria = criteria,
+                local_addrs = self:_local_addresses()
             }
-            send_packet(self, self.next_packet_id, PacketHeader.Join, data)
+            send_request(self, PacketHeader.Join, data)
             self.is_joining = true
             self.join_status = "pending"
         else 
@@ -276,6 +726,130 @@ function lib:join_session(password)
     end
 end
 
+-- Requests a page of public sessions built with the same client,
+-- the reply is available through get_session_list(). Its total is the
+-- number of pages, a page holds fewer sessions when their metadata is large
+function lib:list_sessions(filters, page)
+    if self:check_config() then
+        local data = {
+            filters = filters,
+            page = page
+        }
+
+        self.session_list = nil
+        send_request(self, PacketHeader.List, data)
+    end
+end
+
+function lib:get_session_list()
+    return self.session_list
+end
+
+-- Ask the server which address it sees us as, the reply is not resent so
+-- call this again if get_reflexive_addr() stays empty
+function lib:request_reflexive_address()
+    if self:check_config() then
+        serializer:clear()
+
+        local littleEndian = serializer:endian() == "Little Endian"
+
+        serializer:write_u32(0, false, littleEndian)
+        serializer:write_u16(PacketHeader.Echo, false, littleEndian)
+
+        -- the server won't answer with more bytes than we sent
+        while #serializer.Buffer < MIN_HANDSHAKE_LEN do
+            serializer:write_u8(0)
+        end
+
+        self.socket:send(serializer.Buffer)
+    end
+end
+
+function lib:get_reflexive_addr()
+    return self.reflexive_addr
+end
+
+-- Ask the server to forward traffic to our match when punching failed
+function lib:request_relay()
+    if self:check_config() then
+        if string.len(self.remote_addr) == 0 then
+            self:_debug_print("No match to relay to")
+            return
+        end
+
+        send_request(self, PacketHeader.Relay, {})
+    end
+end
+
+-- Relayed datagrams are unreliable and are never resent
+function lib:send_relay(data)
+    if self.relay_channel == nil then
+        self:_debug_print("No relay channel open")
+        return
+    end
+
+    serializer:clear()
+
+    local littleEndian = serializer:endian() == "Little Endian"
+
+    serializer:write_u32(0, false, littleEndian)
+    serializer:write_u16(PacketHeader.RelayData, false, littleEndian)
+    serializer:write_u32(self.relay_channel, false, littleEndian)
+
+    self.socket:send(serializer.Buffer..data)
+end
+
+-- Returns and clears the datagrams recieved through the relay
+function lib:take_relay_data()
+    local inbox = self.relay_inbox
+    self.relay_inbox = {}
+    return inbox
+end
+
+-- True when the server agreed to a capability, e.g. lib:has_capability("Relay")
+function lib:has_capability(name)
+    return bit.band(self.capabilities, Capability[name] or 0) ~= 0
+end
+
+function lib:get_relay_channel()
+    return self.relay_channel
+end
+
+-- Replaces the metadata of the session we are hosting
+function lib:update_metadata(metadata)
+    if self:check_config() then
+        if string.len(self.session_key) == 0 then
+            self:_debug_print("No session to update")
+            return
+        end
+
+        send_request(self, PacketHeader.Metadata, { metadata = metadata })
+    end
+end
+
+-- Rebinds to a fresh port and reclaims our session, e.g. after the NAT mapping changed.
+-- A ticket only works once, the server's reply carries the next one
+function lib:reconnect()
+    if self:check_config() then
+        if string.len(self.ticket) == 0 then
+            self:_debug_print("No ticket to reconnect with")
+            return
+        end
+
+        if self.socket then
+            self.socket:close()
+        end
+
+        self.socket = socket.udp()
+        self.socket:setoption('reuseaddr',true)
+        self.socket:setsockname('*', 0)
+        self.socket:setpeername(self.ip, self.port)
+        self.socket:settimeout(self.timeout)
+
+        send_packet(self, self.next_packet_id, PacketHeader.Reconnect, { ticket = self.ticket })
+    end
+end
+
 function lib:close_session() 
     if self:check_config() then
         if string.len(self.session_key) == 0 then 
@@ -285,6 +859,7 @@ function lib:close_session()
 
         send_packet(self, self.next_packet_id, PacketHeader.Close, {})
         self.session_key = ""
+        self.restore = nil
     end
 end
 
@@ -317,6 +892,14 @@ function lib:poll()
     end
 end
 
+function lib:_local_addresses()
+    if string.len(self.local_addr) > 0 then
+        return { self.local_addr }
+    end
+
+    return {}
+end
+
 function lib:_debug_print(message)
     if self.debug then 
         print(message)
@@ -331,6 +914,26 @@ function lib:get_remote_addr()
     return self.remote_addr
 end
 
+-- Everyone in our session while it fills up, empty when the host closed it
+function lib:get_roster()
+    return self.roster
+end
+
+-- Everyone else once the session is full, each entry is { addr, local_addrs }
+function lib:get_members()
+    return self.members
+end
+
+-- Private addresses the remote reported, try these first when on the same LAN
+function lib:get_remote_local_addrs()
+    return self.remote_local_addrs
+end
+
+-- Returns true once the server scheduled punch time has been reached
+function lib:should_punch()
+    return self.punch_time ~= nil and socket.gettime() >= self.punch_time
+end
+
 function lib:sleep(seconds)
     socket.sleep(seconds)
 end
@@ -98,6 +98,22 @@ function serializer:read_u32(reversed)
     return bor_ext(l1, l2, l3, l4)
 end
 
+function serializer:read_u64(reversed)
+	-- bitop works on 32 bits, build the value with arithmetic instead
+	local value = 0
+	if reversed then
+		for i = 0, 7 do
+			value = value + self:read_u8() * 2^(8 * i)
+		end
+	else
+		for i = 7, 0, -1 do
+			value = value + self:read_u8() * 2^(8 * i)
+		end
+	end
+
+	return value
+end
+
 function serializer:read_string(reversed)
     local len = self:read_u8()
 	local ret = ""
@@ -0,0 +1,132 @@
+-- sha256 lua module
+-- SHA-256 and HMAC-SHA256 built on the bit ops module
+local bit = bit or require("bit")
+
+local band, bor, bxor, bnot = bit.band, bit.bor, bit.bxor, bit.bnot
+local rshift, lshift, ror, tobit = bit.rshift, bit.lshift, bit.ror, bit.tobit
+
+local lib = {}
+
+local K = {
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+}
+
+-- big endian bytes of a (possibly negative) 32 bit value
+local function u32_bytes(n)
+    n = n % 2^32
+
+    return string.char(
+        math.floor(n / 2^24) % 256,
+        math.floor(n / 2^16) % 256,
+        math.floor(n / 2^8) % 256,
+        n % 256
+    )
+end
+
+local function pad(message)
+    local len = #message
+    local bit_len = len * 8
+
+    return message
+        .."\128"
+        ..string.rep("\0", (55 - len) % 64)
+        ..u32_bytes(math.floor(bit_len / 2^32))
+        ..u32_bytes(bit_len % 2^32)
+end
+
+local function digest_block(message, offset, H)
+    local w = {}
+
+    for j = 0, 15 do
+        local a, b, c, d = message:byte(offset + j * 4, offset + j * 4 + 3)
+        w[j] = bor(lshift(a, 24), lshift(b, 16), lshift(c, 8), d)
+    end
+
+    for j = 16, 63 do
+        local v = w[j - 15]
+        local s0 = bxor(ror(v, 7), ror(v, 18), rshift(v, 3))
+        v = w[j - 2]
+        local s1 = bxor(ror(v, 17), ror(v, 19), rshift(v, 10))
+        w[j] = tobit(w[j - 16] + s0 + w[j - 7] + s1)
+    end
+
+    local a, b, c, d, e, f, g, h = H[1], H[2], H[3], H[4], H[5], H[6], H[7], H[8]
+
+    for j = 0, 63 do
+        local S1 = bxor(ror(e, 6), ror(e, 11), ror(e, 25))
+        local ch = bxor(band(e, f), band(bnot(e), g))
+        local temp1 = tobit(h + S1 + ch + K[j + 1] + w[j])
+        local S0 = bxor(ror(a, 2), ror(a, 13), ror(a, 22))
+        local maj = bxor(band(a, b), band(a, c), band(b, c))
+        local temp2 = tobit(S0 + maj)
+
+        h = g
+        g = f
+        f = e
+        e = tobit(d + temp1)
+        d = c
+        c = b
+        b = a
+        a = tobit(temp1 + temp2)
+    end
+
+    H[1] = tobit(H[1] + a)
+    H[2] = tobit(H[2] + b)
+    H[3] = tobit(H[3] + c)
+    H[4] = tobit(H[4] + d)
+    H[5] = tobit(H[5] + e)
+    H[6] = tobit(H[6] + f)
+    H[7] = tobit(H[7] + g)
+    H[8] = tobit(H[8] + h)
+end
+
+-- Returns the 32 byte binary digest of message
+function lib.sha256(message)
+    local H = {
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+    }
+
+    message = pad(message)
+
+    for offset = 1, #message, 64 do
+        digest_block(message, offset, H)
+    end
+
+    local digest = {}
+    for i = 1, 8 do
+        digest[i] = u32_bytes(H[i])
+    end
+
+    return table.concat(digest)
+end
+
+local function xor_key(key, value)
+    local result = {}
+
+    for i = 1, 64 do
+        result[i] = string.char(bxor(key:byte(i), value))
+    end
+
+    return table.concat(result)
+end
+
+-- Returns the 32 byte binary HMAC-SHA256 of message
+function lib.hmac_sha256(key, message)
+    if #key > 64 then
+        key = lib.sha256(key)
+    end
+
+    key = key..string.rep("\0", 64 - #key)
+
+    return lib.sha256(xor_key(key, 0x5c)..lib.sha256(xor_key(key, 0x36)..message))
+end
+
+return lib
@@ -0,0 +1,96 @@
+use crate::security::BanTarget;
+use std::net::SocketAddr;
+
+pub const USAGE: &str = "Commands: clients, sessions, kick <address>, close <session key>, \
+ban <ip | subnet | client hash> [seconds], unban <ip | subnet | client hash>, bans, reload, stats. \
+Prefix any command with `json` for json output";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json
+}
+
+pub enum AdminCommand {
+    Clients,
+    Sessions,
+    Kick(SocketAddr),
+    Close(String),
+    Ban(BanTarget, Option<u64>),
+    Unban(BanTarget),
+    Bans,
+    Reload,
+    Stats
+}
+
+impl AdminCommand {
+    // One command per line, e.g. `kick 1.2.3.4:5000` or `json sessions`.
+    // The format is returned even for bad commands so the error can use it.
+    pub fn parse(line: &str) -> (OutputFormat, Result<AdminCommand, String>) {
+        let mut args = line.split_whitespace().peekable();
+
+        let format = if args.peek() == Some(&"json") {
+            args.next();
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        };
+
+        let command = match (args.next(), args.next()) {
+            (Some("clients"), None) => Ok(AdminCommand::Clients),
+            (Some("sessions"), None) => Ok(AdminCommand::Sessions),
+            (Some("kick"), Some(address)) => address
+                .parse()
+                .map(AdminCommand::Kick)
+                .map_err(|_| format!("`{}` is not a client address, e.g. 1.2.3.4:5000", address)),
+            (Some("close"), Some(key)) => Ok(AdminCommand::Close(key.to_string())),
+            (Some("ban"), Some(target)) => match (BanTarget::parse(target), args.next().map(str::parse::<u64>)) {
+                (Some(target), None) => Ok(AdminCommand::Ban(target, None)),
+                (Some(target), Some(Ok(duration))) => Ok(AdminCommand::Ban(target, Some(duration))),
+                _ => Err(format!("Can't ban `{}`", line.trim()))
+            },
+            (Some("unban"), Some(target)) => BanTarget::parse(target)
+                .map(AdminCommand::Unban)
+                .ok_or_else(|| format!("Can't unban `{}`", target)),
+            (Some("bans"), None) => Ok(AdminCommand::Bans),
+            (Some("reload"), None) => Ok(AdminCommand::Reload),
+            (Some("stats"), None) => Ok(AdminCommand::Stats),
+            _ => Err(USAGE.to_string())
+        };
+
+        (format, command)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_prefix_picks_the_format() {
+        assert!(matches!(AdminCommand::parse("json clients"), (OutputFormat::Json, Ok(AdminCommand::Clients))));
+        assert!(matches!(AdminCommand::parse("  sessions "), (OutputFormat::Text, Ok(AdminCommand::Sessions))));
+    }
+
+    #[test]
+    fn kick_needs_a_socket_address() {
+        let address: SocketAddr = "1.2.3.4:5000".parse().unwrap();
+
+        assert!(matches!(AdminCommand::parse("kick 1.2.3.4:5000").1, Ok(AdminCommand::Kick(kicked)) if kicked == address));
+        assert!(AdminCommand::parse("kick 1.2.3.4").1.is_err());
+    }
+
+    #[test]
+    fn ban_takes_an_optional_duration() {
+        assert!(matches!(AdminCommand::parse("ban 1.2.3.0/24").1, Ok(AdminCommand::Ban(BanTarget::Subnet(_, 24), None))));
+        assert!(matches!(AdminCommand::parse("ban ABCDEF 60").1, Ok(AdminCommand::Ban(BanTarget::ClientHash(_), Some(60)))));
+        assert!(AdminCommand::parse("ban ABCDEF soon").1.is_err());
+    }
+
+    #[test]
+    fn unknown_or_incomplete_commands_get_the_usage() {
+        // the format survives so the usage can be sent back as json
+        assert!(matches!(AdminCommand::parse("json frobnicate"), (OutputFormat::Json, Err(usage)) if usage == USAGE));
+        assert!(matches!(AdminCommand::parse("close").1, Err(usage) if usage == USAGE));
+        assert!(matches!(AdminCommand::parse("").1, Err(usage) if usage == USAGE));
+    }
+}
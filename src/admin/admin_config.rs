@@ -0,0 +1,21 @@
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr};
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    // commands are not authenticated, so only loopback addresses are accepted
+    pub bind_address: IpAddr,
+    pub port: u16
+}
+
+impl Default for AdminConfig {
+    fn default() -> AdminConfig {
+        AdminConfig {
+            enabled: false,
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 9465
+        }
+    }
+}
\ No newline at end of file
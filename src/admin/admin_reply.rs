@@ -0,0 +1,120 @@
+use super::OutputFormat;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize)]
+pub struct ClientInfo {
+    pub address: String,
+    pub build: Option<String>,
+    pub protocol_version: Option<u16>,
+    // key of the session the client hosts
+    pub session: Option<String>,
+    pub idle_seconds: f32
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub key: String,
+    pub host: String,
+    pub build_group: String,
+    // the host included
+    pub players: usize,
+    pub max_players: u8,
+    pub password_protected: bool,
+    pub age_seconds: u64
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    pub clients: usize,
+    pub sessions: usize,
+    pub relays: usize,
+    pub queued_clients: usize,
+    pub bans: usize,
+    pub sessions_created: u64,
+    pub matches_made: u64,
+    pub join_failures: u64,
+    pub packets_received: u64,
+    pub parse_failures: u64,
+    pub packets_sent: u64,
+    pub packet_resends: u64,
+    pub rate_limits: String
+}
+
+// The answer to one admin command, serialized as e.g. `{"clients":[...]}` or `{"error":"..."}`
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminReply {
+    Clients(Vec<ClientInfo>),
+    Sessions(Vec<SessionInfo>),
+    Bans(Vec<String>),
+    Stats(Stats),
+    Done(String),
+    Error(String)
+}
+
+impl AdminReply {
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_string(),
+            OutputFormat::Json => serde_json::to_string(self).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+        }
+    }
+}
+
+fn or_dash<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or("-".to_string(), |value| value.to_string())
+}
+
+impl fmt::Display for AdminReply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = match self {
+            AdminReply::Clients(clients) => clients
+                .iter()
+                .map(|client| format!("{} build={} protocol={} session={} idle={:.1}s",
+                    client.address,
+                    or_dash(&client.build),
+                    or_dash(&client.protocol_version),
+                    or_dash(&client.session),
+                    client.idle_seconds
+                ))
+                .collect(),
+            AdminReply::Sessions(sessions) => sessions
+                .iter()
+                .map(|session| format!("{} host={} group={} players={}/{} password={} age={}s",
+                    session.key,
+                    session.host,
+                    session.build_group,
+                    session.players,
+                    session.max_players,
+                    session.password_protected,
+                    session.age_seconds
+                ))
+                .collect(),
+            AdminReply::Bans(bans) => bans.clone(),
+            AdminReply::Stats(stats) => vec![
+                format!("clients {}", stats.clients),
+                format!("sessions {}", stats.sessions),
+                format!("relays {}", stats.relays),
+                format!("queued_clients {}", stats.queued_clients),
+                format!("bans {}", stats.bans),
+                format!("sessions_created {}", stats.sessions_created),
+                format!("matches_made {}", stats.matches_made),
+                format!("join_failures {}", stats.join_failures),
+                format!("packets_received {}", stats.packets_received),
+                format!("parse_failures {}", stats.parse_failures),
+                format!("packets_sent {}", stats.packets_sent),
+                format!("packet_resends {}", stats.packet_resends),
+                format!("rate_limits {}", stats.rate_limits)
+            ],
+            AdminReply::Done(message) => vec![message.clone()],
+            AdminReply::Error(message) => vec![format!("error: {}", message)]
+        };
+
+        if lines.is_empty() {
+            return write!(f, "none");
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
\ No newline at end of file
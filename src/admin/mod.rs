@@ -0,0 +1,8 @@
+mod admin_config;
+pub use admin_config::AdminConfig;
+
+mod admin_command;
+pub use admin_command::{AdminCommand, OutputFormat};
+
+mod admin_reply;
+pub use admin_reply::{AdminReply, ClientInfo, SessionInfo, Stats};
\ No newline at end of file
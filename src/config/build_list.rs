@@ -0,0 +1,156 @@
+use super::ConfigError;
+use crate::security::unix_time;
+use serde::Deserialize;
+use toml::value::Datetime;
+
+// Seconds from the unix epoch to midnight UTC of the given date
+fn unix_time_of_date(year: i64, month: i64, day: i64) -> i64 {
+    // days from civil, shifted so the year starts in march and leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    (era * 146097 + day_of_era - 719468) * 86400
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildStatus {
+    Allowed,
+    // still let in, but told to update
+    Deprecated,
+    Blocked
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Build {
+    pub hash: String,
+    pub name: String,
+    // only builds in the same group are matched together
+    pub group: String,
+    status: BuildStatus,
+    // unix seconds, None never expires
+    expiry: Option<u64>
+}
+
+impl Build {
+    // An allowed build that never expires
+    pub fn new(hash: &str, name: &str, group: &str) -> Build {
+        Build {
+            hash: hash.to_string(),
+            name: name.to_string(),
+            group: group.to_string(),
+            status: BuildStatus::Allowed,
+            expiry: None
+        }
+    }
+
+    pub fn with_status(mut self, status: BuildStatus) -> Build {
+        self.status = status;
+        self
+    }
+
+    // `expiry` is in unix seconds
+    pub fn with_expiry(mut self, expiry: u64) -> Build {
+        self.expiry = Some(expiry);
+        self
+    }
+
+    // Expired builds are blocked no matter what their status says
+    pub fn get_status(&self) -> BuildStatus {
+        match self.expiry {
+            Some(expiry) if expiry <= unix_time() => BuildStatus::Blocked,
+            _ => self.status
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildEntry {
+    hash: String,
+    name: Option<String>,
+    group: Option<String>,
+    status: Option<BuildStatus>,
+    // the build stops working at midnight UTC of this date
+    expires: Option<Datetime>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildFile {
+    #[serde(default)]
+    build: Vec<BuildEntry>
+}
+
+const DEFAULT_GROUP: &str = "default";
+
+// Every client build the server knows of, see hashes.toml.
+// A plain list with one hash per line is still read as allowed builds sharing one group.
+#[derive(Default)]
+pub struct BuildList {
+    builds: Vec<Build>
+}
+
+impl BuildList {
+    // For embedders that keep their builds somewhere other than a file
+    pub fn new(builds: Vec<Build>) -> BuildList {
+        BuildList { builds }
+    }
+
+    pub fn load(path: &str) -> Result<BuildList, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+        if !path.ends_with(".toml") {
+            let builds = contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|hash| Build::new(hash, hash, DEFAULT_GROUP))
+                .collect();
+
+            return Ok(BuildList::new(builds));
+        }
+
+        let file: BuildFile = toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e))?;
+        let mut builds: Vec<Build> = Vec::new();
+
+        for entry in file.build {
+            if entry.hash.is_empty() {
+                return Err(ConfigError::Invalid(format!("{} has a build without a hash", path)));
+            }
+
+            if builds.iter().any(|build| build.hash == entry.hash) {
+                return Err(ConfigError::Invalid(format!("{} lists the hash of {} twice", path, entry.name.unwrap_or(entry.hash))));
+            }
+
+            let expiry = match entry.expires {
+                Some(expires) => match expires.date {
+                    Some(date) => Some(unix_time_of_date(date.year.into(), date.month.into(), date.day.into()).max(0) as u64),
+                    None => return Err(ConfigError::Invalid(format!("{} has an expiry without a date", path)))
+                },
+                None => None
+            };
+
+            builds.push(Build {
+                name: entry.name.clone().unwrap_or_else(|| entry.hash.clone()),
+                group: entry.group.unwrap_or_else(|| DEFAULT_GROUP.to_string()),
+                hash: entry.hash,
+                status: entry.status.unwrap_or(BuildStatus::Allowed),
+                expiry
+            });
+        }
+
+        Ok(BuildList::new(builds))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Build> {
+        self.builds.iter()
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Build> {
+        self.builds.iter().find(|build| build.hash == hash)
+    }
+}
\ No newline at end of file
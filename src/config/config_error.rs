@@ -0,0 +1,29 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Argument(String),
+    Invalid(String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {}: {}", path, e),
+            ConfigError::Argument(message) => write!(f, "bad argument, {}", message),
+            ConfigError::Invalid(message) => write!(f, "invalid configuration, {}", message)
+        }
+    }
+}
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::Parse(_, e) => Some(e),
+            ConfigError::Argument(_) | ConfigError::Invalid(_) => None
+        }
+    }
+}
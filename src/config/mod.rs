@@ -0,0 +1,15 @@
+mod build_list;
+pub use build_list::{Build, BuildList, BuildStatus};
+
+mod config_error;
+pub use config_error::ConfigError;
+
+mod server_config;
+pub use server_config::{ServerConfig, NetworkConfig, TimeoutConfig, SessionConfig, FileConfig, LoggingConfig, ShutdownConfig};
+
+// sections owned by the modules they configure
+pub use crate::admin::AdminConfig;
+pub use crate::metrics::MetricsConfig;
+pub use crate::ratelimit::{RateLimitConfig, LimitConfig, BucketConfig};
+pub use crate::relay::RelayConfig;
+pub use crate::snapshot::SnapshotConfig;
\ No newline at end of file
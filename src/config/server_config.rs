@@ -0,0 +1,243 @@
+use super::ConfigError;
+use crate::admin::AdminConfig;
+use crate::metrics::MetricsConfig;
+use crate::ratelimit::{BucketConfig, RateLimitConfig};
+use crate::relay::RelayConfig;
+use crate::snapshot::SnapshotConfig;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use tracing_subscriber::EnvFilter;
+
+// false for NaN as well
+fn is_positive(value: f32) -> bool {
+    value > 0.0
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub bind_address: IpAddr,
+    // 0 until supplied by the file or the command line
+    pub port: u16,
+    pub echo_port: Option<u16>,
+    pub tick_rate: f64
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            echo_port: None,
+            tick_rate: 20.0
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    pub max_silence_duration: f32,
+    pub ping_pong_rate: f32,
+    pub queue_time: f32,
+    pub ranked_queue_time: f32
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> TimeoutConfig {
+        TimeoutConfig {
+            max_silence_duration: 30.0,
+            ping_pong_rate: 5.0,
+            queue_time: 30.0,
+            ranked_queue_time: 120.0
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub key_length: usize,
+    pub key_alphabet: String,
+    pub sessions_per_page: usize
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            key_length: 7,
+            key_alphabet: "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789".to_string(),
+            sessions_per_page: 10
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub hashes: String,
+    pub bans: String
+}
+
+impl Default for FileConfig {
+    fn default() -> FileConfig {
+        FileConfig {
+            hashes: "./hashes.toml".to_string(),
+            bans: "./bans.txt".to_string()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // lowest level logged, optionally per module, e.g. "info,matchmaker::packets=trace"
+    pub level: String,
+    // one json object per event instead of plain text
+    pub json: bool
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig {
+            level: "info".to_string(),
+            json: false
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    // seconds to keep resending the goodbye to clients that haven't acked it
+    pub drain_time: f32,
+    // seconds clients are told to wait before reconnecting, 0 for no restart
+    pub retry_after: u16
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> ShutdownConfig {
+        ShutdownConfig {
+            drain_time: 5.0,
+            retry_after: 30
+        }
+    }
+}
+
+// Everything tunable without a rebuild, see matchmaker.example.toml
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub network: NetworkConfig,
+    pub timeouts: TimeoutConfig,
+    pub sessions: SessionConfig,
+    pub files: FileConfig,
+    pub relay: RelayConfig,
+    pub rate_limit: RateLimitConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub shutdown: ShutdownConfig,
+    pub snapshot: SnapshotConfig
+}
+
+impl ServerConfig {
+    pub fn load(path: &str) -> Result<ServerConfig, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: &str| Err(ConfigError::Invalid(message.to_string()));
+
+        if self.network.port == 0 {
+            return invalid("network.port must be set, e.g. `matchmaker 3000`");
+        }
+
+        if matches!(self.network.echo_port, Some(echo_port) if echo_port == 0 || echo_port == self.network.port) {
+            return invalid("network.echo_port must be a different port than network.port");
+        }
+
+        if !(self.network.tick_rate > 0.0 && self.network.tick_rate <= 1000.0) {
+            return invalid("network.tick_rate must be above 0 and at most 1000");
+        }
+
+        let timeouts = &self.timeouts;
+
+        if [timeouts.max_silence_duration, timeouts.ping_pong_rate, timeouts.queue_time, timeouts.ranked_queue_time]
+            .iter()
+            .any(|timeout| !is_positive(*timeout))
+        {
+            return invalid("timeouts must be above 0");
+        }
+
+        if timeouts.ping_pong_rate >= timeouts.max_silence_duration {
+            return invalid("timeouts.ping_pong_rate must be shorter than timeouts.max_silence_duration");
+        }
+
+        let sessions = &self.sessions;
+        let unique_chars: HashSet<char> = sessions.key_alphabet.chars().collect();
+
+        if !(4..=32).contains(&sessions.key_length) {
+            return invalid("sessions.key_length must be between 4 and 32");
+        }
+
+        if unique_chars.len() < 2 || !sessions.key_alphabet.chars().all(|c| c.is_ascii_graphic()) {
+            return invalid("sessions.key_alphabet needs at least 2 distinct printable ascii characters");
+        }
+
+        if sessions.sessions_per_page == 0 || sessions.sessions_per_page > 32 {
+            return invalid("sessions.sessions_per_page must be between 1 and 32");
+        }
+
+        if self.relay.enabled && (self.relay.max_bytes_per_second == 0 || !is_positive(self.relay.idle_timeout)) {
+            return invalid("relay.max_bytes_per_second and relay.idle_timeout must be above 0");
+        }
+
+        let rate_limit = &self.rate_limit;
+        let buckets: Vec<&BucketConfig> = [&rate_limit.connect, &rate_limit.create, &rate_limit.join]
+            .iter()
+            .flat_map(|limits| vec![&limits.per_ip, &limits.per_subnet, &limits.global])
+            .collect();
+
+        if buckets.iter().any(|bucket| bucket.capacity == 0 || !is_positive(bucket.refill_per_second)) {
+            return invalid("rate_limit bucket capacities and refill rates must be above 0");
+        }
+
+        if rate_limit.ipv4_subnet_prefix > 32 || rate_limit.ipv6_subnet_prefix > 128 {
+            return invalid("rate_limit subnet prefixes must be at most 32 for ipv4 and 128 for ipv6");
+        }
+
+        if rate_limit.ignore_duration.is_nan() || rate_limit.ignore_duration < 0.0 {
+            return invalid("rate_limit.ignore_duration must not be negative");
+        }
+
+        if self.metrics.enabled && self.metrics.port == 0 {
+            return invalid("metrics.port must be set while metrics are enabled");
+        }
+
+        if self.admin.enabled && self.admin.port == 0 {
+            return invalid("admin.port must be set while the admin interface is enabled");
+        }
+
+        if !self.admin.bind_address.is_loopback() {
+            return invalid("admin.bind_address must be a loopback address, admin commands are not authenticated");
+        }
+
+        if self.shutdown.drain_time.is_nan() || self.shutdown.drain_time < 0.0 {
+            return invalid("shutdown.drain_time must not be negative");
+        }
+
+        if !is_positive(self.snapshot.interval) || self.snapshot.grace_period.is_nan() || self.snapshot.grace_period < 0.0 {
+            return invalid("snapshot.interval must be above 0 and snapshot.grace_period must not be negative");
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.logging.level) {
+            return Err(ConfigError::Invalid(format!("logging.level `{}` is not understood: {}", self.logging.level, e)));
+        }
+
+        Ok(())
+    }
+}
\ No newline at end of file
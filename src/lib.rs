@@ -0,0 +1,31 @@
+//! Embed the matchmaker in another process, e.g.
+//!
+//! ```no_run
+//! use matchmaker::config::ServerConfig;
+//! use matchmaker::Server;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let config = ServerConfig::load("./matchmaker.toml")?;
+//!     let mut server = Server::builder(config)
+//!         .on_event(|event| println!("{:?}", event))
+//!         .build()?;
+//!
+//!     Server::poll(&mut server)?;
+//!     Ok(())
+//! }
+//! ```
+
+mod admin;
+pub mod config;
+pub mod logging;
+mod matchmaking;
+mod metrics;
+pub mod packets;
+mod ratelimit;
+mod relay;
+mod security;
+mod server;
+mod snapshot;
+mod threads;
+
+pub use server::{Server, ServerBuilder, ServerEvent, ShutdownHandle};
\ No newline at end of file
@@ -0,0 +1,38 @@
+use crate::config::LoggingConfig;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+use tracing_subscriber::prelude::*;
+
+// Swaps the level filter of the running logger
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>
+}
+
+impl LogHandle {
+    pub fn set_level(&self, level: &str) {
+        match EnvFilter::try_new(level) {
+            Ok(filter) => {
+                if let Err(e) = self.filter.reload(filter) {
+                    tracing::error!(error = %e, "Failed to change the log level");
+                }
+            },
+            Err(e) => tracing::error!(level, error = %e, "Invalid log level")
+        }
+    }
+}
+
+// `level` takes per module directives, e.g. "info,matchmaker::packets=trace"
+pub fn init_logging(config: &LoggingConfig) -> LogHandle {
+    // validated with the rest of the config, fall back to info just in case
+    let filter = EnvFilter::try_new(&config.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let registry = tracing_subscriber::registry().with(filter);
+
+    if config.json {
+        registry.with(fmt::layer().json()).init();
+    } else {
+        registry.with(fmt::layer()).init();
+    }
+
+    LogHandle { filter: handle }
+}
\ No newline at end of file
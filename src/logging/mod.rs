@@ -0,0 +1,2 @@
+mod log_setup;
+pub use log_setup::{init_logging, LogHandle};
\ No newline at end of file
@@ -0,0 +1,53 @@
+use crate::packets::{Metadata, MetadataValue};
+
+// Well known criteria keys relax the longer a joiner waits,
+// any other key has to match the session metadata exactly
+pub const REGION_KEY: &str = "region";
+pub const RATING_KEY: &str = "rating";
+
+const REGION_RELAX_TIME: f32 = 10.0;
+// shared by the criteria queue and the ranked queue
+const RATING_WINDOW: f32 = 50.0;
+const RATING_WINDOW_GROWTH: f32 = 10.0;
+const MAX_RATING_WINDOW: f32 = 500.0;
+
+// Rating difference accepted after waiting `wait_time` seconds, it stops widening at the cap
+pub fn rating_window(wait_time: f32) -> f32 {
+    (RATING_WINDOW + RATING_WINDOW_GROWTH * wait_time).min(MAX_RATING_WINDOW)
+}
+
+pub fn is_compatible(criteria: &Metadata, metadata: &Metadata, wait_time: f32) -> bool {
+    criteria.iter().all(|(key, value)| {
+        match key.as_str() {
+            REGION_KEY if wait_time >= REGION_RELAX_TIME => true,
+            RATING_KEY => match (value, metadata.get(RATING_KEY)) {
+                (MetadataValue::Int(rating), Some(MetadataValue::Int(host_rating))) => {
+                    (i64::from(*rating) - i64::from(*host_rating)).abs() as f32 <= rating_window(wait_time)
+                },
+                // unrated sessions accept anyone
+                _ => true
+            },
+            _ => metadata.get(key) == Some(value)
+        }
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rated(rating: i32) -> Metadata {
+        serde_json::from_value(serde_json::json!({ RATING_KEY: { "Int": rating } })).unwrap()
+    }
+
+    #[test]
+    fn rating_window_widens_with_the_wait() {
+        assert!(!is_compatible(&rated(1500), &rated(1600), 0.0));
+        assert!(is_compatible(&rated(1500), &rated(1600), 5.0));
+    }
+
+    #[test]
+    fn rating_window_stops_at_the_cap() {
+        assert_eq!(rating_window(3600.0), MAX_RATING_WINDOW);
+        assert!(!is_compatible(&rated(1000), &rated(2000), 3600.0));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::packets::Metadata;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+pub struct QueuedJoiner {
+    pub socket_address: SocketAddr,
+    pub build_group: String,
+    pub criteria: Metadata,
+    pub queue_time: Instant
+}
+
+// Joiners looking for any open session, longest waiting first
+#[derive(Default)]
+pub struct MatchQueue {
+    joiners: Vec<QueuedJoiner>
+}
+
+impl MatchQueue {
+    pub fn len(&self) -> usize {
+        self.joiners.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&QueuedJoiner> {
+        self.joiners.get(index)
+    }
+
+    // Requeueing keeps the original wait time so a client can update its criteria
+    pub fn push(&mut self, socket_address: SocketAddr, build_group: String, criteria: Metadata) {
+        if let Some(joiner) = self.joiners.iter_mut().find(|joiner| joiner.socket_address == socket_address) {
+            joiner.build_group = build_group;
+            joiner.criteria = criteria;
+            return;
+        }
+
+        self.joiners.push(QueuedJoiner {
+            socket_address,
+            build_group,
+            criteria,
+            queue_time: Instant::now()
+        });
+    }
+
+    pub fn remove(&mut self, index: usize) -> QueuedJoiner {
+        self.joiners.remove(index)
+    }
+
+    pub fn migrate_client(&mut self, old_address: &SocketAddr, new_address: &SocketAddr) {
+        for entry in self.joiners.iter_mut().filter(|entry| entry.socket_address == *old_address) {
+            entry.socket_address = *new_address;
+        }
+    }
+
+    pub fn remove_client(&mut self, socket_address: &SocketAddr) -> bool {
+        let len = self.joiners.len();
+        self.joiners.retain(|joiner| joiner.socket_address != *socket_address);
+        len != self.joiners.len()
+    }
+
+    // Removes and returns every joiner that waited longer than max_wait
+    pub fn expire(&mut self, max_wait: f32) -> Vec<SocketAddr> {
+        let (expired, waiting): (Vec<QueuedJoiner>, Vec<QueuedJoiner>) = self
+            .joiners
+            .drain(..)
+            .partition(|joiner| joiner.queue_time.elapsed().as_secs_f32() > max_wait);
+
+        self.joiners = waiting;
+
+        expired.into_iter().map(|joiner| joiner.socket_address).collect()
+    }
+}
\ No newline at end of file
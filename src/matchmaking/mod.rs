@@ -0,0 +1,8 @@
+mod match_criteria;
+pub use match_criteria::is_compatible;
+
+mod match_queue;
+pub use match_queue::MatchQueue;
+
+mod rating_queue;
+pub use rating_queue::RatingQueue;
\ No newline at end of file
@@ -0,0 +1,169 @@
+use crate::packets::{Metadata, MetadataValue};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+use super::match_criteria::{rating_window, RATING_KEY};
+
+pub const RANKED_KEY: &str = "ranked";
+pub const PLAYER_ID_KEY: &str = "player_id";
+
+const DEFAULT_RATING: i32 = 1500;
+// seconds a player id's rating is remembered after it last queued
+const RATING_MEMORY: f32 = 24.0 * 60.0 * 60.0;
+const MAX_REMEMBERED_RATINGS: usize = 10_000;
+
+struct QueuedPlayer {
+    socket_address: SocketAddr,
+    build_group: String,
+    player_id: String,
+    rating: i32,
+    criteria: Metadata,
+    queue_time: Instant
+}
+
+impl QueuedPlayer {
+    fn rating_window(&self) -> f32 {
+        rating_window(self.queue_time.elapsed().as_secs_f32())
+    }
+
+    // Anything besides the rating and identity has to agree, e.g. game mode
+    fn shares_queue_with(&self, other: &QueuedPlayer) -> bool {
+        let is_shared = |a: &Metadata, b: &Metadata| {
+            a.iter()
+                .filter(|(key, _)| key.as_str() != RATING_KEY && key.as_str() != PLAYER_ID_KEY)
+                .all(|(key, value)| b.get(key) == Some(value))
+        };
+
+        self.build_group == other.build_group
+            && self.player_id != other.player_id
+            && is_shared(&self.criteria, &other.criteria)
+            && is_shared(&other.criteria, &self.criteria)
+    }
+}
+
+// Ranked 1v1 queue, players are paired within a rating window that widens while they wait
+#[derive(Default)]
+pub struct RatingQueue {
+    players: Vec<QueuedPlayer>,
+    // last known rating and queue time per player id, used when a client doesn't send a rating
+    ratings: HashMap<String, (i32, Instant)>
+}
+
+impl RatingQueue {
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_ranked(criteria: &Metadata) -> bool {
+        criteria.get(RANKED_KEY) == Some(&MetadataValue::Bool(true))
+    }
+
+    // Returns false when the criteria don't identify the player
+    pub fn push(&mut self, socket_address: SocketAddr, build_group: String, criteria: Metadata) -> bool {
+        let player_id = match criteria.get(PLAYER_ID_KEY) {
+            Some(MetadataValue::Str(player_id)) if !player_id.is_empty() => player_id.clone(),
+            _ => return false
+        };
+
+        let rating = match criteria.get(RATING_KEY) {
+            Some(MetadataValue::Int(rating)) => *rating,
+            _ => self.ratings.get(&player_id).map_or(DEFAULT_RATING, |(rating, _)| *rating)
+        };
+
+        if !self.ratings.contains_key(&player_id) {
+            self.forget_ratings();
+        }
+
+        self.ratings.insert(player_id.clone(), (rating, Instant::now()));
+        self.remove_client(&socket_address);
+
+        self.players.push(QueuedPlayer {
+            socket_address,
+            build_group,
+            player_id,
+            rating,
+            criteria,
+            queue_time: Instant::now()
+        });
+
+        true
+    }
+
+    pub fn migrate_client(&mut self, old_address: &SocketAddr, new_address: &SocketAddr) {
+        for entry in self.players.iter_mut().filter(|entry| entry.socket_address == *old_address) {
+            entry.socket_address = *new_address;
+        }
+    }
+
+    pub fn remove_client(&mut self, socket_address: &SocketAddr) -> bool {
+        let len = self.players.len();
+        self.players.retain(|player| player.socket_address != *socket_address);
+        len != self.players.len()
+    }
+
+    // Pairs the longest waiting players first with their closest rated opponent
+    pub fn find_pairs(&mut self) -> Vec<(SocketAddr, SocketAddr)> {
+        let mut pairs = Vec::new();
+        let mut index = 0;
+
+        while index < self.players.len() {
+            let player = &self.players[index];
+
+            let opponent = self.players
+                .iter()
+                .enumerate()
+                .skip(index + 1)
+                .filter(|(_, other)| {
+                    let difference = (i64::from(player.rating) - i64::from(other.rating)).abs() as f32;
+
+                    player.shares_queue_with(other)
+                        && difference <= player.rating_window().min(other.rating_window())
+                })
+                .min_by_key(|(_, other)| (i64::from(player.rating) - i64::from(other.rating)).abs())
+                .map(|(opponent_index, _)| opponent_index);
+
+            if let Some(opponent_index) = opponent {
+                let opponent = self.players.remove(opponent_index);
+                let player = self.players.remove(index);
+
+                pairs.push((player.socket_address, opponent.socket_address));
+            } else {
+                index += 1;
+            }
+        }
+
+        pairs
+    }
+
+    // Makes room for another player id, stale ratings go first and the oldest one if that wasn't enough
+    fn forget_ratings(&mut self) {
+        if self.ratings.len() < MAX_REMEMBERED_RATINGS {
+            return;
+        }
+
+        self.ratings.retain(|_, (_, last_queued)| last_queued.elapsed().as_secs_f32() < RATING_MEMORY);
+
+        if self.ratings.len() >= MAX_REMEMBERED_RATINGS {
+            let oldest = self.ratings
+                .iter()
+                .min_by_key(|(_, (_, last_queued))| *last_queued)
+                .map(|(player_id, _)| player_id.clone());
+
+            if let Some(player_id) = oldest {
+                self.ratings.remove(&player_id);
+            }
+        }
+    }
+
+    // Removes and returns every player that waited longer than max_wait
+    pub fn expire(&mut self, max_wait: f32) -> Vec<SocketAddr> {
+        let (expired, waiting): (Vec<QueuedPlayer>, Vec<QueuedPlayer>) = self
+            .players
+            .drain(..)
+            .partition(|player| player.queue_time.elapsed().as_secs_f32() > max_wait);
+
+        self.players = waiting;
+
+        expired.into_iter().map(|player| player.socket_address).collect()
+    }
+}
\ No newline at end of file
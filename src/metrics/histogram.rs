@@ -0,0 +1,53 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+
+struct Observations {
+    // one count per bound, not cumulative until rendered
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64
+}
+
+// Fixed bucket histogram, `bounds` are upper bounds in ascending order
+pub struct Histogram {
+    bounds: &'static [f64],
+    observations: Mutex<Observations>
+}
+
+impl Histogram {
+    pub const fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            observations: Mutex::new(Observations { counts: Vec::new(), sum: 0.0, count: 0 })
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let mut observations = self.observations.lock().unwrap();
+
+        if observations.counts.is_empty() {
+            observations.counts = vec![0; self.bounds.len()];
+        }
+
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            observations.counts[index] += 1;
+        }
+
+        observations.sum += value;
+        observations.count += 1;
+    }
+
+    pub fn render(&self, out: &mut String, name: &str) {
+        let observations = self.observations.lock().unwrap();
+        let mut cumulative = 0;
+
+        for (index, bound) in self.bounds.iter().enumerate() {
+            cumulative += observations.counts.get(index).copied().unwrap_or(0);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, observations.count);
+        let _ = writeln!(out, "{}_sum {}", name, observations.sum);
+        let _ = writeln!(out, "{}_count {}", name, observations.count);
+    }
+}
\ No newline at end of file
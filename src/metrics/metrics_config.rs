@@ -0,0 +1,21 @@
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr};
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // scraped over plain http, keep it on loopback unless the network is trusted
+    pub bind_address: IpAddr,
+    pub port: u16
+}
+
+impl Default for MetricsConfig {
+    fn default() -> MetricsConfig {
+        MetricsConfig {
+            enabled: true,
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 9464
+        }
+    }
+}
\ No newline at end of file
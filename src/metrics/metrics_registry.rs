@@ -0,0 +1,142 @@
+use super::Histogram;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SESSION_WAIT_BOUNDS: [f64; 9] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+const ROUND_TRIP_BOUNDS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // For counts kept by the server thread and copied over on every tick
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub const fn new() -> Gauge {
+        Gauge(AtomicU64::new(0))
+    }
+
+    pub fn set(&self, value: usize) {
+        self.0.store(value as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// One per server, shared with its threads and updated without locking
+pub struct Metrics {
+    pub packets_received: Counter,
+    pub parse_failures: Counter,
+    pub packets_sent: Counter,
+    pub packet_resends: Counter,
+    pub sessions_created: Counter,
+    pub matches_made: Counter,
+    pub join_failures: Counter,
+    pub relays_opened: Counter,
+    pub connects_limited: Counter,
+    pub creates_limited: Counter,
+    pub joins_limited: Counter,
+    pub addresses_ignored: Counter,
+    pub packets_ignored: Counter,
+    pub connected_clients: Gauge,
+    pub open_sessions: Gauge,
+    pub open_relays: Gauge,
+    pub queued_clients: Gauge,
+    // seconds from creating a session until it started
+    pub session_wait_time: Histogram,
+    // seconds from first sending a data packet until its ack, resends included
+    pub ack_round_trip_time: Histogram
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            packets_received: Counter::new(),
+            parse_failures: Counter::new(),
+            packets_sent: Counter::new(),
+            packet_resends: Counter::new(),
+            sessions_created: Counter::new(),
+            matches_made: Counter::new(),
+            join_failures: Counter::new(),
+            relays_opened: Counter::new(),
+            connects_limited: Counter::new(),
+            creates_limited: Counter::new(),
+            joins_limited: Counter::new(),
+            addresses_ignored: Counter::new(),
+            packets_ignored: Counter::new(),
+            connected_clients: Gauge::new(),
+            open_sessions: Gauge::new(),
+            open_relays: Gauge::new(),
+            queued_clients: Gauge::new(),
+            session_wait_time: Histogram::new(&SESSION_WAIT_BOUNDS),
+            ack_round_trip_time: Histogram::new(&ROUND_TRIP_BOUNDS)
+        }
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            ("matchmaker_packets_received_total", "Datagrams read by the listening thread", &self.packets_received),
+            ("matchmaker_packet_parse_failures_total", "Datagrams that could not be parsed", &self.parse_failures),
+            ("matchmaker_packets_sent_total", "Data packets sent to clients, resends excluded", &self.packets_sent),
+            ("matchmaker_packet_resends_total", "Unacknowledged data packets sent again", &self.packet_resends),
+            ("matchmaker_sessions_created_total", "Sessions created by hosts", &self.sessions_created),
+            ("matchmaker_matches_made_total", "Sessions and ranked pairs that started", &self.matches_made),
+            ("matchmaker_join_failures_total", "Join requests that were refused or timed out", &self.join_failures),
+            ("matchmaker_relays_opened_total", "Relay channels opened", &self.relays_opened),
+            ("matchmaker_connects_limited_total", "Hellos refused by the rate limiter", &self.connects_limited),
+            ("matchmaker_creates_limited_total", "Session creates refused by the rate limiter", &self.creates_limited),
+            ("matchmaker_joins_limited_total", "Joins refused by the rate limiter", &self.joins_limited),
+            ("matchmaker_addresses_ignored_total", "Addresses ignored for exceeding their rate limit", &self.addresses_ignored),
+            ("matchmaker_packets_ignored_total", "Datagrams dropped from ignored addresses", &self.packets_ignored)
+        ];
+
+        for (name, help, counter) in counters.iter() {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, counter.get());
+        }
+
+        let gauges = [
+            ("matchmaker_connected_clients", "Clients currently known to the server", &self.connected_clients),
+            ("matchmaker_open_sessions", "Sessions waiting for players", &self.open_sessions),
+            ("matchmaker_open_relays", "Relay channels currently open", &self.open_relays),
+            ("matchmaker_queued_clients", "Clients waiting in the match and ranked queues", &self.queued_clients)
+        ];
+
+        for (name, help, gauge) in gauges.iter() {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, gauge.get());
+        }
+
+        let histograms = [
+            ("matchmaker_session_wait_seconds", "Time from creating a session until it started", &self.session_wait_time),
+            ("matchmaker_ack_round_trip_seconds", "Time from sending a data packet until it was acknowledged", &self.ack_round_trip_time)
+        ];
+
+        for (name, help, histogram) in histograms.iter() {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+            histogram.render(&mut out, name);
+        }
+
+        out
+    }
+}
\ No newline at end of file
@@ -0,0 +1,8 @@
+mod metrics_config;
+pub use metrics_config::MetricsConfig;
+
+mod histogram;
+pub use histogram::Histogram;
+
+mod metrics_registry;
+pub use metrics_registry::Metrics;
\ No newline at end of file
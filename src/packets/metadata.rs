@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use super::{read_byte, read_u32, read_string_u8, write_u32, write_string_u8};
+
+pub const MAX_METADATA_ENTRIES: usize = 16;
+pub const MAX_METADATA_KEY_LEN: usize = 32;
+pub const MAX_METADATA_VALUE_LEN: usize = 64;
+// encoded bytes, small enough that a session listing always fits in a list page
+pub const MAX_METADATA_LEN: usize = 400;
+
+enum MetadataType {
+    Bool = 0,
+    Int = 1,
+    Str = 2
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MetadataValue {
+    Bool(bool),
+    Int(i32),
+    Str(String)
+}
+
+// Host supplied key/value pairs, e.g. lobby name, game mode, region or a ranked flag
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadata {
+    entries: BTreeMap<String, MetadataValue>
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.entries.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &MetadataValue)> {
+        self.entries.iter()
+    }
+
+    pub fn is_within_limits(&self) -> bool {
+        let entries_within_limits = self.entries.len() <= MAX_METADATA_ENTRIES
+            && self.entries.iter().all(|(key, value)| {
+                key.len() <= MAX_METADATA_KEY_LEN
+                && match value {
+                    MetadataValue::Str(string) => string.len() <= MAX_METADATA_VALUE_LEN,
+                    _ => true
+                }
+            });
+
+        entries_within_limits && self.encoded_len() <= MAX_METADATA_LEN
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let mut buf = Vec::new();
+        write_metadata(&mut buf, self);
+        buf.len()
+    }
+
+    // Every filter entry has to be present with the same value
+    pub fn matches(&self, filters: &Metadata) -> bool {
+        filters
+            .entries
+            .iter()
+            .all(|(key, value)| self.entries.get(key) == Some(value))
+    }
+}
+
+pub fn read_metadata(buf: &mut &[u8]) -> Option<Metadata> {
+    let len = read_byte(buf)?;
+    let mut entries = BTreeMap::new();
+
+    for _ in 0..len {
+        let key = read_string_u8(buf)?;
+
+        let value = match read_byte(buf)? {
+            0 => MetadataValue::Bool(read_byte(buf)? != 0),
+            1 => MetadataValue::Int(read_u32(buf)? as i32),
+            2 => MetadataValue::Str(read_string_u8(buf)?),
+            _ => return None
+        };
+
+        entries.insert(key, value);
+    }
+
+    Some(Metadata { entries })
+}
+
+pub fn write_metadata(buf: &mut Vec<u8>, metadata: &Metadata) {
+    buf.push(metadata.entries.len().min(u8::MAX.into()) as u8);
+
+    for (key, value) in metadata.entries.iter().take(u8::MAX.into()) {
+        write_string_u8(buf, key);
+
+        match value {
+            MetadataValue::Bool(data) => {
+                buf.push(MetadataType::Bool as u8);
+                buf.push(*data as u8);
+            },
+            MetadataValue::Int(data) => {
+                buf.push(MetadataType::Int as u8);
+                write_u32(buf, *data as u32);
+            },
+            MetadataValue::Str(data) => {
+                buf.push(MetadataType::Str as u8);
+                write_string_u8(buf, data);
+            }
+        }
+    }
+}
@@ -1,2 +1,9 @@
+#[allow(clippy::module_inception)]
 mod packets;
-pub use packets::*;
\ No newline at end of file
+pub use packets::*;
+
+mod metadata;
+pub use metadata::*;
+
+mod protocol;
+pub use protocol::*;
\ No newline at end of file
@@ -1,21 +1,86 @@
 use std::net::{UdpSocket, SocketAddr};
-use crate::threads::clock_thread::TICK_RATE;
-use num_derive;
+use super::{Metadata, read_metadata, write_metadata};
+use crate::metrics::Metrics;
+use std::sync::Arc;
+
+// the lua client reads datagrams of up to this many bytes
+pub const MAX_CLIENT_DATAGRAM_LEN: usize = 512;
+
+// packet type and id in front of every data packet
+const DATA_HEADER_LEN: usize = 5;
 
 // enums
-#[derive(num_derive::FromPrimitive)]
 enum PacketId {
     PingPong = 0,
     Ack = 1,
     Create = 2,
     Join = 3,
     Close = 4,
-    Error = 5
+    Error = 5,
+    Punch = 6,
+    Relay = 7,
+    Echo = 9,
+    Roster = 10,
+    Start = 11,
+    List = 12,
+    Hello = 14,
+    Auth = 15,
+    Reconnect = 16,
+    Retry = 17
 }
 
+#[allow(clippy::enum_variant_names)]
 enum PacketType {
     AckPacket = 0,
-    DataPacket = 1
+    DataPacket = 1,
+    RelayPacket = 2,
+    EchoPacket = 3,
+    RetryPacket = 4
+}
+
+#[derive(Clone, Copy)]
+pub enum ErrorReason {
+    SessionCreateFailed = 0,
+    InvalidPassword = 1,
+    RelayUnavailable = 2,
+    InvalidMetadata = 3,
+    UnsupportedVersion = 4,
+    HandshakeRequired = 5,
+    AuthenticationFailed = 6,
+    TicketRejected = 7,
+    RateLimited = 8,
+    TooManyAttempts = 9,
+    Banned = 10,
+    BuildBlocked = 11,
+    BuildDeprecated = 12,
+    IncompatibleBuild = 13,
+    ServerShutdown = 14,
+    PlayerIdRequired = 15,
+    RestoreRejected = 16
+}
+
+pub struct SessionListing {
+    pub session_key: String,
+    pub age: u32,
+    pub players: u8,
+    pub max_players: u8,
+    pub metadata: Metadata
+}
+
+impl SessionListing {
+    fn write(&self, buf: &mut Vec<u8>) {
+        write_string_u8(buf, &self.session_key);
+        write_u32(buf, self.age);
+        buf.push(self.players);
+        buf.push(self.max_players);
+        write_metadata(buf, &self.metadata);
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut buf = Vec::new();
+        self.write(&mut buf);
+        buf.len()
+    }
 }
 
 pub enum ServerPacket<'a> {
@@ -23,16 +88,53 @@ pub enum ServerPacket<'a> {
     Ack {
         id: u32
     },
+    Welcome {
+        protocol_version: u16,
+        capabilities: u32,
+        nonce: &'a [u8]
+    },
+    Auth {
+        success: bool
+    },
     Create {
-        session_key: &'a str
+        session_key: &'a str,
+        ticket: &'a [u8],
+        restore_token: &'a [u8]
     },
     Join {
         client_addr: Option<&'a SocketAddr>,
+        local_addrs: &'a [SocketAddr],
+        ticket: &'a [u8],
         success: bool
     },
+    Reconnect {
+        ticket: &'a [u8]
+    },
+    Punch {
+        server_time: u64,
+        start_time: u64
+    },
+    Relay {
+        channel: u32,
+        active: bool
+    },
+    Roster {
+        session_key: &'a str,
+        max_players: u8,
+        members: &'a [SocketAddr]
+    },
+    Start {
+        members: &'a [(SocketAddr, Vec<SocketAddr>)]
+    },
+    List {
+        page: u16,
+        total: u16,
+        sessions: &'a [SessionListing]
+    },
     Close,
     Error {
         id: u32,
+        reason: ErrorReason,
         message: &'a str
     }
 }
@@ -43,17 +145,78 @@ pub enum ClientPacket {
     Ack {
         id: u32
     },
+    Hello {
+        protocol_version: u16,
+        capabilities: u32,
+        cookie: Vec<u8>
+    },
+    Auth {
+        mac: Vec<u8>
+    },
+    Reconnect {
+        ticket: Vec<u8>,
+        cookie: Vec<u8>
+    },
+    Restore {
+        session_key: String,
+        token: Vec<u8>
+    },
     Create {
-        client_hash: String,
-        password_protected: bool
+        password: String,
+        password_after: u8,
+        max_players: u8,
+        metadata: Metadata,
+        local_addrs: Vec<String>
     },
     Join {
-        client_hash: String,
-        session_key: String
+        session_key: String,
+        password: String,
+        criteria: Metadata,
+        local_addrs: Vec<String>
+    },
+    Metadata {
+        metadata: Metadata
+    },
+    List {
+        filters: Metadata,
+        page: u16
+    },
+    Echo,
+    RelayRequest,
+    RelayData {
+        channel: u32,
+        data: Vec<u8>
     },
     Close
 }
 
+impl ClientPacket {
+    // Anything past Hello needs a negotiated protocol version first
+    pub fn requires_handshake(&self) -> bool {
+        matches!(self, ClientPacket::Auth { .. }) || self.requires_auth()
+    }
+
+    // Only these may open a connection, the cookie proves the sender owns its address
+    pub fn get_cookie(&self) -> Option<&[u8]> {
+        match self {
+            ClientPacket::Hello { cookie, .. } | ClientPacket::Reconnect { cookie, .. } => Some(cookie),
+            _ => None
+        }
+    }
+
+    // Anything touching sessions needs an authenticated client build
+    pub fn requires_auth(&self) -> bool {
+        matches!(self,
+            ClientPacket::Create { .. }
+            | ClientPacket::Restore { .. }
+            | ClientPacket::Join { .. }
+            | ClientPacket::Metadata { .. }
+            | ClientPacket::List { .. }
+            | ClientPacket::RelayRequest
+        )
+    }
+}
+
 // packets
 
 pub struct Packet {
@@ -67,44 +230,47 @@ pub struct Packet {
 pub struct PacketShipper {
     socket_address: SocketAddr,
     next_id: u32,
-    backed_up: Vec<Packet>
+    backed_up: Vec<Packet>,
+    metrics: Arc<Metrics>
 }
 
 impl PacketShipper {
-    pub fn new(socket_address: SocketAddr) -> PacketShipper {
+    pub fn new(socket_address: SocketAddr, metrics: Arc<Metrics>) -> PacketShipper {
         PacketShipper {
             socket_address,
             next_id: 0,
-            backed_up: Vec::new()
+            backed_up: Vec::new(),
+            metrics
         }
     }
 
-    pub fn send(&mut self, socket: &UdpSocket, packet: &ServerPacket) {
+    pub fn send(&mut self, socket: &UdpSocket, packet: &ServerPacket) -> u32 {
+        let id = self.next_id;
+
         let mut data = vec![];
 
         data.push(PacketType::DataPacket as u8);
 
-        write_u32(&mut data, self.next_id);
+        write_u32(&mut data, id);
         data.extend(build_server_packet(packet));
 
-        println!("Buffer: {:?}", data);
+        tracing::trace!(client = %self.socket_address, id, bytes = ?data, "Sent packet");
 
         let _ = socket.send_to(&data, self.socket_address);
-
-        println!("After socket send to {} ", self.socket_address);
+        self.metrics.packets_sent.increment();
 
         self.backed_up.push(Packet {
-            id: self.next_id,
+            id,
             creation_time: std::time::Instant::now(),
             data
         });
 
         self.next_id += 1;
-    }
 
-    pub fn resend_unacknowledged_packets(&self, socket: &UdpSocket) {
-        let retry_delay = std::time::Duration::from_secs_f64(1.0 / TICK_RATE);
+        id
+    }
 
+    pub fn resend_unacknowledged_packets(&self, socket: &UdpSocket, retry_delay: std::time::Duration) {
         let iter = self
             .backed_up
             .iter()
@@ -117,15 +283,28 @@ impl PacketShipper {
                 // socket buffer is probably full
                 break;
             }
+
+            self.metrics.packet_resends.increment();
         }
     }
 
+    pub fn set_socket_address(&mut self, socket_address: SocketAddr) {
+        self.socket_address = socket_address;
+    }
+
+    pub fn get_unacknowledged_count(&self) -> usize {
+        self.backed_up.len()
+    }
+
+    pub fn is_acknowledged(&self, id: u32) -> bool {
+        !self.backed_up.iter().any(|packet| packet.id == id)
+    }
+
     pub fn acknowledge(&mut self, id: u32) {
-        self
-        .backed_up
-        .iter()
-        .position(|packet| packet.id == id)
-        .map(|position| self.backed_up.remove(position));
+        if let Some(position) = self.backed_up.iter().position(|packet| packet.id == id) {
+            let packet = self.backed_up.remove(position);
+            self.metrics.ack_round_trip_time.observe(packet.creation_time.elapsed().as_secs_f64());
+        }
     }
 }
 
@@ -159,6 +338,14 @@ impl PacketReciever {
         &self.last_message_time
     }
 
+    pub fn set_socket_address(&mut self, socket_address: SocketAddr) {
+        self.socket_address = socket_address;
+    }
+
+    pub fn touch(&mut self) {
+        self.last_message_time = std::time::Instant::now();
+    }
+
     pub fn sort_packets(&mut self,
         socket: &UdpSocket,
         id: u32,
@@ -190,7 +377,8 @@ impl PacketReciever {
             )
         );
 
-        println!("Buffer: {:?}", data);
+        tracing::trace!(client = %self.socket_address, id, bytes = ?data, "Sent ack");
+
         let _ = socket.send_to(&data, self.socket_address);
     }
 }
@@ -209,6 +397,7 @@ pub fn read_byte(buf: &mut &[u8]) -> Option<u8> {
     Some(byte)
 }
 
+#[allow(dead_code)]
 pub fn read_bool(buf: &mut &[u8]) -> Option<bool> {
     read_byte(buf).map(|byte| byte != 0)
 }
@@ -243,11 +432,60 @@ pub fn read_u32(buf: &mut &[u8]) -> Option<u32> {
     Some(data)
 }
 
+pub fn read_u64(buf: &mut &[u8]) -> Option<u64> {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    if buf.len() < 8 {
+        *buf = &buf[buf.len()..];
+        return None;
+    }
+
+    let data = LittleEndian::read_u64(buf);
+
+    *buf = &buf[8..];
+
+    Some(data)
+}
+
 pub fn read_string_u8(buf: &mut &[u8]) -> Option<String> {
     let len = read_byte(buf)? as usize;
     read_string(buf, len)
 }
 
+pub fn read_string_list_u8(buf: &mut &[u8]) -> Option<Vec<String>> {
+    let len = read_byte(buf)? as usize;
+    let mut list = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        list.push(read_string_u8(buf)?);
+    }
+
+    Some(list)
+}
+
+pub fn read_remaining(buf: &mut &[u8]) -> Vec<u8> {
+    let data = buf.to_vec();
+
+    *buf = &buf[buf.len()..];
+
+    data
+}
+
+pub fn read_bytes_u8(buf: &mut &[u8]) -> Option<Vec<u8>> {
+    let len = read_byte(buf)? as usize;
+
+    if buf.len() < len {
+        *buf = &buf[buf.len()..];
+        return None;
+    }
+
+    let data = buf[..len].to_vec();
+
+    *buf = &buf[len..];
+
+    Some(data)
+}
+
 fn read_string(buf: &mut &[u8], len: usize) -> Option<String> {
     if buf.len() < len {
         *buf = &buf[buf.len()..];
@@ -264,13 +502,13 @@ fn read_string(buf: &mut &[u8], len: usize) -> Option<String> {
 }
 
 fn parse_headers(buf: &mut &[u8]) -> Option<u32> {
-    Some(read_u32(buf)?)
+    read_u32(buf)
 }
 
 fn parse_packet(buf: &mut &[u8]) -> Option<ClientPacket> {
     let packet_type = read_u16(buf)?;
 
-    println!("packet_type: {}", packet_type);
+    tracing::trace!(packet_type, "Parsing packet");
 
     match packet_type {
         0 => Some(ClientPacket::Pong),
@@ -278,14 +516,49 @@ fn parse_packet(buf: &mut &[u8]) -> Option<ClientPacket> {
             id: read_u32(buf)?
         }),
         2 => Some(ClientPacket::Create {
-            client_hash: read_string_u8(buf)?,
-            password_protected: read_bool(buf)?
+            password: read_string_u8(buf)?,
+            password_after: read_byte(buf)?,
+            max_players: read_byte(buf)?,
+            metadata: read_metadata(buf)?,
+            local_addrs: read_string_list_u8(buf)?
         }),
         3 => Some(ClientPacket::Join{
-            client_hash: read_string_u8(buf)?,
-            session_key: read_string_u8(buf)?
+            session_key: read_string_u8(buf)?,
+            password: read_string_u8(buf)?,
+            criteria: read_metadata(buf)?,
+            local_addrs: read_string_list_u8(buf)?
         }),
         4 => Some(ClientPacket::Close),
+        7 => Some(ClientPacket::RelayRequest),
+        8 => Some(ClientPacket::RelayData {
+            channel: read_u32(buf)?,
+            data: read_remaining(buf)
+        }),
+        9 => Some(ClientPacket::Echo),
+        12 => Some(ClientPacket::List {
+            filters: read_metadata(buf)?,
+            page: read_u16(buf)?
+        }),
+        13 => Some(ClientPacket::Metadata {
+            metadata: read_metadata(buf)?
+        }),
+        14 => Some(ClientPacket::Hello {
+            protocol_version: read_u16(buf)?,
+            capabilities: read_u32(buf)?,
+            // clients from before the cookie round trip still need to parse so they can be told to update
+            cookie: read_bytes_u8(buf).unwrap_or_default()
+        }),
+        15 => Some(ClientPacket::Auth {
+            mac: read_bytes_u8(buf)?
+        }),
+        16 => Some(ClientPacket::Reconnect {
+            ticket: read_bytes_u8(buf)?,
+            cookie: read_bytes_u8(buf)?
+        }),
+        18 => Some(ClientPacket::Restore {
+            session_key: read_string_u8(buf)?,
+            token: read_bytes_u8(buf)?
+        }),
         _ => None
     }
 }
@@ -317,6 +590,14 @@ pub fn write_u32(buf: &mut Vec<u8>, data: u32) {
     buf.extend(&buf_32);
 }
 
+pub fn write_u64(buf: &mut Vec<u8>, data: u64) {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    let mut buf_64 = [0u8; 8];
+    LittleEndian::write_u64(&mut buf_64, data);
+    buf.extend(&buf_64);
+}
+
 pub fn write_string_u8(buf: &mut Vec<u8>, data: &str) {
     let len = if data.len() < u8::MAX.into() {
         data.len() as u8
@@ -328,6 +609,21 @@ pub fn write_string_u8(buf: &mut Vec<u8>, data: &str) {
     buf.extend(&data.as_bytes()[0..len.into()]);
 }
 
+pub fn write_bytes_u8(buf: &mut Vec<u8>, data: &[u8]) {
+    let len = data.len().min(u8::MAX.into());
+
+    buf.push(len as u8);
+    buf.extend(&data[..len]);
+}
+
+pub fn write_addr_list_u8(buf: &mut Vec<u8>, data: &[SocketAddr]) {
+    buf.push(data.len().min(u8::MAX.into()) as u8);
+
+    for addr in data.iter().take(u8::MAX.into()) {
+        write_string_u8(buf, &addr.to_string());
+    }
+}
+
 pub fn build_server_packet(packet: &ServerPacket) -> Vec<u8> {
     let mut vec = Vec::new();
     let buf = &mut vec;
@@ -340,27 +636,197 @@ pub fn build_server_packet(packet: &ServerPacket) -> Vec<u8> {
             write_u16(buf, PacketId::Ack as u16);
             write_u32(buf, *id);
         },
-        ServerPacket::Create { session_key } => {
+        ServerPacket::Welcome { protocol_version, capabilities, nonce } => {
+            write_u16(buf, PacketId::Hello as u16);
+            write_u16(buf, *protocol_version);
+            write_u32(buf, *capabilities);
+            write_bytes_u8(buf, nonce);
+        },
+        ServerPacket::Auth { success } => {
+            write_u16(buf, PacketId::Auth as u16);
+            write_bool(buf, *success);
+        },
+        ServerPacket::Create { session_key, ticket, restore_token } => {
             write_u16(buf, PacketId::Create as u16);
-            write_string_u8(buf, *session_key);
+            write_string_u8(buf, session_key);
+            write_bytes_u8(buf, ticket);
+            write_bytes_u8(buf, restore_token);
         },
-        ServerPacket::Join { client_addr, success } => {
+        ServerPacket::Join { client_addr, local_addrs, ticket, success } => {
             write_u16(buf, PacketId::Join as u16);
             write_bool(buf, *success);
 
             if *success {
                 write_string_u8(buf, &client_addr.unwrap().to_string());
+                write_addr_list_u8(buf, local_addrs);
+                write_bytes_u8(buf, ticket);
+            }
+        },
+        ServerPacket::Reconnect { ticket } => {
+            write_u16(buf, PacketId::Reconnect as u16);
+            write_bytes_u8(buf, ticket);
+        },
+        ServerPacket::Roster { session_key, max_players, members } => {
+            write_u16(buf, PacketId::Roster as u16);
+            write_string_u8(buf, session_key);
+            buf.push(*max_players);
+            write_addr_list_u8(buf, members);
+        },
+        ServerPacket::Start { members } => {
+            write_u16(buf, PacketId::Start as u16);
+            buf.push(members.len().min(u8::MAX.into()) as u8);
+
+            for (addr, local_addrs) in members.iter().take(u8::MAX.into()) {
+                write_string_u8(buf, &addr.to_string());
+                write_addr_list_u8(buf, local_addrs);
+            }
+        },
+        ServerPacket::Punch { server_time, start_time } => {
+            write_u16(buf, PacketId::Punch as u16);
+            write_u64(buf, *server_time);
+            write_u64(buf, *start_time);
+        },
+        ServerPacket::Relay { channel, active } => {
+            write_u16(buf, PacketId::Relay as u16);
+            write_u32(buf, *channel);
+            write_bool(buf, *active);
+        },
+        ServerPacket::List { page, total, sessions } => {
+            write_u16(buf, PacketId::List as u16);
+            write_u16(buf, *page);
+            write_u16(buf, *total);
+            buf.push(sessions.len().min(u8::MAX.into()) as u8);
+
+            for session in sessions.iter().take(u8::MAX.into()) {
+                session.write(buf);
             }
         },
         ServerPacket::Close => {
             write_u16(buf, PacketId::Close as u16);
         },
-        ServerPacket::Error { id, message } => {
+        ServerPacket::Error { id, reason, message } => {
             write_u16(buf, PacketId::Error as u16);
             write_u32(buf, *id);
-            write_string_u8(buf, *message);
+            write_u16(buf, *reason as u16);
+            write_string_u8(buf, message);
         }
     }
 
     vec
-}
\ No newline at end of file
+}
+
+// Length of the datagram PacketShipper::send makes of the packet
+pub fn data_packet_len(packet: &ServerPacket) -> usize {
+    DATA_HEADER_LEN + build_server_packet(packet).len()
+}
+
+// Splits listings into pages of at most max_per_page that each fit in a datagram the client can read
+pub fn paginate_listings(listings: Vec<SessionListing>, max_per_page: usize) -> Vec<Vec<SessionListing>> {
+    let empty_page_len = data_packet_len(&ServerPacket::List { page: 0, total: 0, sessions: &[] });
+
+    let mut pages: Vec<Vec<SessionListing>> = Vec::new();
+    let mut page_len = 0;
+
+    for listing in listings {
+        let listing_len = listing.encoded_len();
+
+        match pages.last_mut() {
+            Some(page) if page.len() < max_per_page && page_len + listing_len <= MAX_CLIENT_DATAGRAM_LEN => {
+                page_len += listing_len;
+                page.push(listing);
+            },
+            _ => {
+                page_len = empty_page_len + listing_len;
+                pages.push(vec![listing]);
+            }
+        }
+    }
+
+    pages
+}
+
+// Drops local address candidates, first from whoever has the most, until a Start fits in a datagram
+// the client can read. Public addresses are always kept, MAX_PLAYERS of them fit on their own
+pub fn fit_start_members(members: &mut [(SocketAddr, Vec<SocketAddr>)]) {
+    while data_packet_len(&ServerPacket::Start { members }) > MAX_CLIENT_DATAGRAM_LEN {
+        let most_candidates = members
+            .iter_mut()
+            .map(|(_, local_addrs)| local_addrs)
+            .filter(|local_addrs| !local_addrs.is_empty())
+            .max_by_key(|local_addrs| local_addrs.len());
+
+        match most_candidates {
+            Some(local_addrs) => {
+                local_addrs.pop();
+            },
+            None => break
+        }
+    }
+}
+
+// relayed datagrams are unreliable and carry the payload untouched
+pub fn build_relay_packet(channel: u32, data: &[u8]) -> Vec<u8> {
+    let mut vec = Vec::with_capacity(data.len() + 5);
+
+    vec.push(PacketType::RelayPacket as u8);
+    write_u32(&mut vec, channel);
+    vec.extend(data);
+
+    vec
+}
+
+// retry replies are stateless, the client repeats its first packet with the cookie attached
+pub fn build_retry_packet(cookie: &[u8]) -> Vec<u8> {
+    let mut vec = Vec::new();
+
+    vec.push(PacketType::RetryPacket as u8);
+    write_u16(&mut vec, PacketId::Retry as u16);
+    write_bytes_u8(&mut vec, cookie);
+
+    vec
+}
+
+// echo replies are stateless, clients resend the request if it is lost
+pub fn build_echo_packet(client_addr: &SocketAddr) -> Vec<u8> {
+    let mut vec = Vec::new();
+
+    vec.push(PacketType::EchoPacket as u8);
+    write_u16(&mut vec, PacketId::Echo as u16);
+    write_string_u8(&mut vec, &client_addr.to_string());
+
+    vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(i: u16, local_addrs: usize) -> (SocketAddr, Vec<SocketAddr>) {
+        let local_addrs = (0..local_addrs as u16)
+            .map(|j| SocketAddr::from(([192, 168, 100, 100 + j as u8], 50000 + j)))
+            .collect();
+
+        (SocketAddr::from(([203, 0, 113, 200], 60000 + i)), local_addrs)
+    }
+
+    #[test]
+    fn full_session_start_fits_in_a_client_datagram() {
+        let mut members: Vec<_> = (0..7).map(|i| peer(i, 4)).collect();
+        assert!(data_packet_len(&ServerPacket::Start { members: &members }) > MAX_CLIENT_DATAGRAM_LEN);
+
+        fit_start_members(&mut members);
+
+        assert!(data_packet_len(&ServerPacket::Start { members: &members }) <= MAX_CLIENT_DATAGRAM_LEN);
+        assert_eq!(members.len(), 7);
+        // candidates are taken evenly, nobody loses all of theirs first
+        assert!(members.iter().all(|(_, local_addrs)| !local_addrs.is_empty()));
+    }
+
+    #[test]
+    fn small_start_is_left_alone() {
+        let mut members = vec![peer(0, 4)];
+        fit_start_members(&mut members);
+
+        assert_eq!(members[0].1.len(), 4);
+    }
+}
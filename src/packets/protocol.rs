@@ -0,0 +1,23 @@
+// Bump PROTOCOL_VERSION whenever the wire format changes, and raise
+// MIN_PROTOCOL_VERSION once older clients should no longer be accepted
+//   1  Hello/Welcome handshake
+//   2  Welcome carries a nonce for Auth, Create/Join/List no longer carry the client hash
+//   3  Create and Join replies carry a reconnect ticket
+//   4  Hello carries the address validation cookie
+//   5  Create replies carry a restore token, hosts present it in Restore after a restart
+pub const PROTOCOL_VERSION: u16 = 5;
+pub const MIN_PROTOCOL_VERSION: u16 = 5;
+
+// Capability bits exchanged in Hello/Welcome
+pub const CAPABILITY_RELAY: u32 = 1;
+pub const CAPABILITY_ECHO_PORT: u32 = 1 << 1;
+pub const CAPABILITY_RANKED: u32 = 1 << 2;
+
+// Returns the version both sides speak, or None if the client is too old
+pub fn negotiate_version(client_version: u16) -> Option<u16> {
+    if client_version < MIN_PROTOCOL_VERSION {
+        return None;
+    }
+
+    Some(client_version.min(PROTOCOL_VERSION))
+}
\ No newline at end of file
@@ -0,0 +1,8 @@
+mod token_bucket;
+pub use token_bucket::TokenBucket;
+
+mod rate_limit_config;
+pub use rate_limit_config::{RateLimitConfig, LimitConfig, BucketConfig};
+
+mod rate_limiter;
+pub use rate_limiter::{RateLimiter, LimitKind};
\ No newline at end of file
@@ -0,0 +1,55 @@
+use serde::Deserialize;
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    pub capacity: u32,
+    pub refill_per_second: f32
+}
+
+// Limits for one kind of request, each bucket has to have a token left
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitConfig {
+    pub per_ip: BucketConfig,
+    pub per_subnet: BucketConfig,
+    pub global: BucketConfig
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub connect: LimitConfig,
+    pub create: LimitConfig,
+    pub join: LimitConfig,
+    pub ipv4_subnet_prefix: u8,
+    pub ipv6_subnet_prefix: u8,
+    pub ignore_duration: f32
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: true,
+            connect: LimitConfig {
+                per_ip: BucketConfig { capacity: 10, refill_per_second: 0.5 },
+                per_subnet: BucketConfig { capacity: 50, refill_per_second: 2.0 },
+                global: BucketConfig { capacity: 500, refill_per_second: 100.0 }
+            },
+            create: LimitConfig {
+                per_ip: BucketConfig { capacity: 5, refill_per_second: 0.2 },
+                per_subnet: BucketConfig { capacity: 20, refill_per_second: 1.0 },
+                global: BucketConfig { capacity: 200, refill_per_second: 50.0 }
+            },
+            join: LimitConfig {
+                per_ip: BucketConfig { capacity: 10, refill_per_second: 1.0 },
+                per_subnet: BucketConfig { capacity: 50, refill_per_second: 5.0 },
+                global: BucketConfig { capacity: 500, refill_per_second: 100.0 }
+            },
+            ipv4_subnet_prefix: 24,
+            ipv6_subnet_prefix: 48,
+            ignore_duration: 60.0
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,233 @@
+use super::{LimitConfig, RateLimitConfig, TokenBucket};
+use crate::security::mask_ip;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LimitKind {
+    Connect,
+    Create,
+    Join
+}
+
+#[derive(Default)]
+pub struct RateLimitCounters {
+    pub connects_limited: u64,
+    pub creates_limited: u64,
+    pub joins_limited: u64,
+    pub addresses_ignored: u64,
+    pub packets_ignored: u64
+}
+
+impl fmt::Display for RateLimitCounters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "connects limited: {}, creates limited: {}, joins limited: {}, addresses ignored: {}, packets ignored: {}",
+            self.connects_limited,
+            self.creates_limited,
+            self.joins_limited,
+            self.addresses_ignored,
+            self.packets_ignored
+        )
+    }
+}
+
+// Token buckets per source ip, per subnet and server wide for every limited request kind.
+// An ip that runs dry of its own bucket is ignored entirely for a while.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    ip_buckets: HashMap<(LimitKind, IpAddr), TokenBucket>,
+    subnet_buckets: HashMap<(LimitKind, IpAddr), TokenBucket>,
+    global_buckets: HashMap<LimitKind, TokenBucket>,
+    ignored: HashMap<IpAddr, Instant>,
+    counters: RateLimitCounters
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            ip_buckets: HashMap::new(),
+            subnet_buckets: HashMap::new(),
+            global_buckets: HashMap::new(),
+            ignored: HashMap::new(),
+            counters: RateLimitCounters::default()
+        }
+    }
+
+    // Buckets already handed out keep their old size until they refill
+    pub fn set_config(&mut self, config: RateLimitConfig) {
+        self.config = config;
+    }
+
+    pub fn get_counters(&self) -> &RateLimitCounters {
+        &self.counters
+    }
+
+    pub fn is_ignored(&mut self, ip: &IpAddr) -> bool {
+        let ignored = match self.ignored.get(ip) {
+            Some(until) => *until > Instant::now(),
+            None => false
+        };
+
+        if ignored {
+            self.counters.packets_ignored += 1;
+        }
+
+        ignored
+    }
+
+    // Returns false if the request must be refused. Only an ip over its own limit is then ignored for a while
+    pub fn check(&mut self, kind: LimitKind, ip: &IpAddr) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+
+        let subnet = self.get_subnet(ip);
+        let limits = RateLimiter::get_limits(&self.config, kind);
+
+        // every bucket is charged so a busy subnet can't hide behind fresh ips
+        let ip_allowed = self.ip_buckets
+            .entry((kind, *ip))
+            .or_insert_with(|| TokenBucket::new(&limits.per_ip))
+            .take();
+
+        let subnet_allowed = self.subnet_buckets
+            .entry((kind, subnet))
+            .or_insert_with(|| TokenBucket::new(&limits.per_subnet))
+            .take();
+
+        let global_allowed = self.global_buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::new(&limits.global))
+            .take();
+
+        if ip_allowed && subnet_allowed && global_allowed {
+            return true;
+        }
+
+        match kind {
+            LimitKind::Connect => self.counters.connects_limited += 1,
+            LimitKind::Create => self.counters.creates_limited += 1,
+            LimitKind::Join => self.counters.joins_limited += 1
+        }
+
+        // an empty subnet or global bucket isn't the fault of this ip alone, only this request is refused
+        if !ip_allowed {
+            let until = Instant::now() + std::time::Duration::from_secs_f32(self.config.ignore_duration);
+
+            if self.ignored.insert(*ip, until).is_none() {
+                self.counters.addresses_ignored += 1;
+            }
+
+            tracing::warn!(ip = %ip, seconds = self.config.ignore_duration, kind = ?kind, "Ignoring address after exceeding rate limits");
+        }
+
+        false
+    }
+
+    // Forgets buckets that refilled and ignores that ran out
+    pub fn update(&mut self) {
+        let now = Instant::now();
+
+        self.ip_buckets.retain(|_, bucket| !bucket.is_full());
+        self.subnet_buckets.retain(|_, bucket| !bucket.is_full());
+        self.ignored.retain(|_, until| *until > now);
+    }
+
+    fn get_limits(config: &RateLimitConfig, kind: LimitKind) -> &LimitConfig {
+        match kind {
+            LimitKind::Connect => &config.connect,
+            LimitKind::Create => &config.create,
+            LimitKind::Join => &config.join
+        }
+    }
+
+    fn get_subnet(&self, ip: &IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(_) => mask_ip(ip, self.config.ipv4_subnet_prefix),
+            IpAddr::V6(_) => mask_ip(ip, self.config.ipv6_subnet_prefix)
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratelimit::BucketConfig;
+
+    // no refill, so every test sees exactly `capacity` tokens per bucket
+    fn limiter(per_ip: u32, per_subnet: u32, global: u32) -> RateLimiter {
+        let bucket = |capacity| BucketConfig { capacity, refill_per_second: 0.0 };
+        let config = RateLimitConfig {
+            connect: LimitConfig {
+                per_ip: bucket(per_ip),
+                per_subnet: bucket(per_subnet),
+                global: bucket(global)
+            },
+            ..RateLimitConfig::default()
+        };
+
+        RateLimiter::new(config)
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([198, 51, 100, last])
+    }
+
+    #[test]
+    fn ip_over_its_own_limit_is_ignored() {
+        let mut limiter = limiter(2, 10, 10);
+
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+        assert!(!limiter.check(LimitKind::Connect, &ip(1)));
+
+        assert!(limiter.is_ignored(&ip(1)));
+        assert!(!limiter.is_ignored(&ip(2)));
+        assert_eq!(limiter.get_counters().connects_limited, 1);
+        assert_eq!(limiter.get_counters().addresses_ignored, 1);
+    }
+
+    #[test]
+    fn empty_subnet_bucket_refuses_without_ignoring() {
+        let mut limiter = limiter(10, 2, 10);
+
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+        assert!(limiter.check(LimitKind::Connect, &ip(2)));
+        // a neighbour in the same /24 pays for the others
+        assert!(!limiter.check(LimitKind::Connect, &ip(3)));
+
+        assert!(!limiter.is_ignored(&ip(3)));
+        assert_eq!(limiter.get_counters().addresses_ignored, 0);
+
+        // other subnets still get through
+        assert!(limiter.check(LimitKind::Connect, &IpAddr::from([203, 0, 113, 1])));
+    }
+
+    #[test]
+    fn empty_global_bucket_refuses_without_ignoring() {
+        let mut limiter = limiter(10, 10, 1);
+
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+        assert!(!limiter.check(LimitKind::Connect, &IpAddr::from([203, 0, 113, 1])));
+
+        assert!(!limiter.is_ignored(&IpAddr::from([203, 0, 113, 1])));
+    }
+
+    #[test]
+    fn kinds_are_limited_separately() {
+        let mut limiter = limiter(1, 10, 10);
+
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+        assert!(limiter.check(LimitKind::Join, &ip(1)));
+    }
+
+    #[test]
+    fn disabled_limiter_allows_everything() {
+        let mut limiter = limiter(0, 0, 0);
+        limiter.config.enabled = false;
+
+        assert!(limiter.check(LimitKind::Connect, &ip(1)));
+    }
+}
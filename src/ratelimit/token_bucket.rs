@@ -0,0 +1,44 @@
+use super::BucketConfig;
+use std::time::Instant;
+
+// Allows bursts up to `capacity`, then `refill_per_second` on average
+pub struct TokenBucket {
+    capacity: f32,
+    refill_per_second: f32,
+    tokens: f32,
+    last_refill: Instant
+}
+
+impl TokenBucket {
+    pub fn new(config: &BucketConfig) -> TokenBucket {
+        TokenBucket {
+            capacity: config.capacity as f32,
+            refill_per_second: config.refill_per_second,
+            tokens: config.capacity as f32,
+            last_refill: Instant::now()
+        }
+    }
+
+    // A full bucket carries no history and can be dropped
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+
+    pub fn take(&mut self) -> bool {
+        self.refill();
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn refill(&mut self) {
+        let elapsed = self.last_refill.elapsed().as_secs_f32();
+        self.last_refill = Instant::now();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,5 @@
+mod relay_channel;
+pub use relay_channel::RelayChannel;
+
+mod relay_config;
+pub use relay_config::RelayConfig;
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+
+// Forwards datagrams between a matched pair that could not punch through
+pub struct RelayChannel {
+    id: u32,
+    peers: [SocketAddr; 2],
+    max_bytes_per_second: f32,
+    budget: f32,
+    last_refill: Instant,
+    last_activity: Instant
+}
+
+impl RelayChannel {
+    pub fn new(id: u32, peers: [SocketAddr; 2], max_bytes_per_second: u32) -> RelayChannel {
+        let max_bytes_per_second = max_bytes_per_second as f32;
+
+        RelayChannel {
+            id,
+            peers,
+            max_bytes_per_second,
+            budget: max_bytes_per_second,
+            last_refill: Instant::now(),
+            last_activity: Instant::now()
+        }
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_peers(&self) -> &[SocketAddr; 2] {
+        &self.peers
+    }
+
+    pub fn has_peer(&self, socket_address: &SocketAddr) -> bool {
+        self.peers.contains(socket_address)
+    }
+
+    pub fn migrate_peer(&mut self, old_address: &SocketAddr, new_address: &SocketAddr) {
+        for peer in self.peers.iter_mut().filter(|peer| *peer == old_address) {
+            *peer = *new_address;
+        }
+    }
+
+    pub fn get_last_activity(&self) -> &Instant {
+        &self.last_activity
+    }
+
+    // Returns where the datagram should go, or None if it must be dropped
+    pub fn route(&mut self, sender: &SocketAddr, len: usize) -> Option<SocketAddr> {
+        let destination = if *sender == self.peers[0] {
+            self.peers[1]
+        } else if *sender == self.peers[1] {
+            self.peers[0]
+        } else {
+            return None;
+        };
+
+        // refill the bandwidth budget, allowing at most a second of burst
+        let elapsed = self.last_refill.elapsed().as_secs_f32();
+        self.last_refill = Instant::now();
+        self.budget = (self.budget + elapsed * self.max_bytes_per_second).min(self.max_bytes_per_second);
+
+        if self.budget < len as f32 {
+            return None;
+        }
+
+        self.budget -= len as f32;
+        self.last_activity = Instant::now();
+
+        Some(destination)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    pub enabled: bool,
+    pub max_relays: usize,
+    pub max_bytes_per_second: u32,
+    pub idle_timeout: f32
+}
+
+impl Default for RelayConfig {
+    fn default() -> RelayConfig {
+        RelayConfig {
+            enabled: true,
+            max_relays: 256,
+            max_bytes_per_second: 64 * 1024,
+            idle_timeout: 30.0
+        }
+    }
+}
\ No newline at end of file
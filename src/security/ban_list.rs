@@ -0,0 +1,239 @@
+use super::{mask_ip, unix_time};
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+
+#[derive(PartialEq)]
+pub enum BanTarget {
+    Ip(IpAddr),
+    Subnet(IpAddr, u8),
+    ClientHash(String)
+}
+
+impl BanTarget {
+    // `1.2.3.4`, `1.2.3.0/24` or anything else as a client hash
+    pub fn parse(target: &str) -> Option<BanTarget> {
+        if target.is_empty() {
+            return None;
+        }
+
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return Some(BanTarget::Ip(ip));
+        }
+
+        if let Some((ip, prefix)) = target.split_once('/') {
+            let ip = ip.parse::<IpAddr>().ok()?;
+            let prefix = prefix.parse::<u8>().ok()?;
+            return Some(BanTarget::Subnet(mask_ip(&ip, prefix), prefix));
+        }
+
+        Some(BanTarget::ClientHash(target.to_string()))
+    }
+
+    pub fn matches_ip(&self, ip: &IpAddr) -> bool {
+        match self {
+            BanTarget::Ip(banned) => banned == ip,
+            BanTarget::Subnet(subnet, prefix) => mask_ip(ip, *prefix) == *subnet,
+            BanTarget::ClientHash(_) => false
+        }
+    }
+
+    pub fn matches_hash(&self, hash: &str) -> bool {
+        matches!(self, BanTarget::ClientHash(banned) if banned == hash)
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BanTarget::Ip(ip) => write!(f, "{}", ip),
+            BanTarget::Subnet(subnet, prefix) => write!(f, "{}/{}", subnet, prefix),
+            BanTarget::ClientHash(hash) => write!(f, "{}", hash)
+        }
+    }
+}
+
+pub struct Ban {
+    target: BanTarget,
+    // unix seconds, None never expires
+    expiry: Option<u64>
+}
+
+impl Ban {
+    fn is_expired(&self) -> bool {
+        matches!(self.expiry, Some(expiry) if expiry <= unix_time())
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expiry {
+            Some(expiry) => write!(f, "{} {}", self.target, expiry),
+            None => write!(f, "{}", self.target)
+        }
+    }
+}
+
+// One ban per line, `<ip | subnet | client hash> [expiry in unix seconds]`.
+// Lines starting with # are ignored.
+#[derive(Default)]
+pub struct BanList {
+    path: String,
+    bans: Vec<Ban>
+}
+
+impl BanList {
+    // A missing file is an empty ban list
+    pub fn load(path: &str) -> BanList {
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        let mut bans = Vec::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+
+            let target = match parts.next().and_then(BanTarget::parse) {
+                Some(target) => target,
+                None => continue
+            };
+
+            let expiry = match parts.next().map(str::parse::<u64>) {
+                Some(Ok(expiry)) => Some(expiry),
+                Some(Err(_)) => {
+                    tracing::warn!(path, line, "Skipping ban with a malformed expiry");
+                    continue;
+                },
+                None => None
+            };
+
+            let ban = Ban { target, expiry };
+
+            if !ban.is_expired() {
+                bans.push(ban);
+            }
+        }
+
+        tracing::info!(path, bans = bans.len(), "Loaded bans");
+
+        BanList {
+            path: path.to_string(),
+            bans
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Ban> {
+        self.bans.iter()
+    }
+
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        self.bans.iter().any(|ban| !ban.is_expired() && ban.target.matches_ip(ip))
+    }
+
+    pub fn is_hash_banned(&self, hash: &str) -> bool {
+        self.bans.iter().any(|ban| !ban.is_expired() && ban.target.matches_hash(hash))
+    }
+
+    // Replaces any ban on the same target, `duration` in seconds or None for good
+    pub fn add(&mut self, target: BanTarget, duration: Option<u64>) {
+        self.bans.retain(|ban| ban.target != target);
+        self.bans.push(Ban {
+            target,
+            expiry: duration.map(|duration| unix_time() + duration)
+        });
+
+        self.save();
+    }
+
+    pub fn remove(&mut self, target: &BanTarget) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|ban| ban.target != *target);
+
+        if self.bans.len() == len {
+            return false;
+        }
+
+        self.save();
+        true
+    }
+
+    pub fn update(&mut self) {
+        let len = self.bans.len();
+        self.bans.retain(|ban| !ban.is_expired());
+
+        if self.bans.len() != len {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        if self.path.is_empty() {
+            return;
+        }
+
+        let contents: String = self.bans.iter().map(|ban| ban.to_string() + "\n").collect();
+
+        if let Err(e) = fs::write(&self.path, contents) {
+            tracing::error!(path = %self.path, error = %e, "Failed to save bans");
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ban_list() -> BanList {
+        // an empty path is never written to
+        BanList { path: String::new(), bans: Vec::new() }
+    }
+
+    #[test]
+    fn subnet_ban_covers_its_neighbours() {
+        let mut bans = ban_list();
+        bans.add(BanTarget::parse("198.51.100.7/24").unwrap(), None);
+
+        assert!(bans.is_ip_banned(&"198.51.100.200".parse().unwrap()));
+        assert!(!bans.is_ip_banned(&"198.51.101.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn hash_ban_leaves_ips_alone() {
+        let mut bans = ban_list();
+        bans.add(BanTarget::parse("ABCDEF").unwrap(), None);
+
+        assert!(bans.is_hash_banned("ABCDEF"));
+        assert!(!bans.is_ip_banned(&"198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn expired_bans_no_longer_apply() {
+        let mut bans = ban_list();
+        bans.bans.push(Ban { target: BanTarget::parse("198.51.100.1").unwrap(), expiry: Some(unix_time() - 1) });
+
+        assert!(!bans.is_ip_banned(&"198.51.100.1".parse().unwrap()));
+
+        bans.update();
+        assert_eq!(bans.iter().count(), 0);
+    }
+
+    #[test]
+    fn bans_survive_a_reload() {
+        let path = std::env::temp_dir().join(format!("matchmaker-ban-list-{}.txt", std::process::id()));
+        let path = path.to_string_lossy();
+
+        let mut bans = BanList::load(&path);
+        bans.add(BanTarget::parse("198.51.100.1").unwrap(), Some(600));
+        bans.add(BanTarget::parse("ABCDEF").unwrap(), None);
+
+        let loaded = BanList::load(&path);
+        let _ = fs::remove_file(&*path);
+
+        assert!(loaded.is_ip_banned(&"198.51.100.1".parse().unwrap()));
+        assert!(loaded.is_hash_banned("ABCDEF"));
+
+        assert!(bans.remove(&BanTarget::parse("ABCDEF").unwrap()));
+        assert!(!bans.remove(&BanTarget::parse("ABCDEF").unwrap()));
+    }
+}
@@ -0,0 +1,20 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+pub const NONCE_LEN: usize = 16;
+
+pub fn generate_nonce() -> [u8; NONCE_LEN] {
+    rand::thread_rng().gen()
+}
+
+// Clients prove they were built with `secret` by returning HMAC-SHA256(secret, nonce)
+pub fn verify_response(secret: &str, nonce: &[u8], mac: &[u8]) -> bool {
+    let mut hmac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(hmac) => hmac,
+        Err(_) => return false
+    };
+
+    hmac.update(nonce);
+    hmac.verify_slice(mac).is_ok()
+}
\ No newline at end of file
@@ -0,0 +1,6 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Wall clock seconds, for anything that has to outlive a restart (bans, tickets, snapshots, build expiry)
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
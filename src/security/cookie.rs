@@ -0,0 +1,97 @@
+use super::unix_time;
+use crate::packets::{read_u64, write_u64};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use std::net::SocketAddr;
+
+const COOKIE_LIFETIME: u64 = 30;
+const COOKIE_MAC_LEN: usize = 16;
+
+// Proves a new address can recieve our packets before any state is kept for it,
+// spoofed sources never see the cookie so they can't complete the round trip
+pub struct AddressValidator {
+    secret: [u8; 32]
+}
+
+impl AddressValidator {
+    pub fn new() -> AddressValidator {
+        AddressValidator {
+            secret: rand::thread_rng().gen()
+        }
+    }
+
+    pub fn issue(&self, socket_address: &SocketAddr) -> Vec<u8> {
+        let mut cookie = Vec::new();
+        let issued = unix_time();
+
+        write_u64(&mut cookie, issued);
+        cookie.extend(&self.mac(socket_address, issued).finalize().into_bytes()[..COOKIE_MAC_LEN]);
+
+        cookie
+    }
+
+    pub fn verify(&self, socket_address: &SocketAddr, cookie: &[u8]) -> bool {
+        let mut buf = cookie;
+
+        let issued = match read_u64(&mut buf) {
+            Some(issued) => issued,
+            None => return false
+        };
+
+        if buf.len() != COOKIE_MAC_LEN || unix_time().saturating_sub(issued) > COOKIE_LIFETIME {
+            return false;
+        }
+
+        self.mac(socket_address, issued).verify_truncated_left(buf).is_ok()
+    }
+
+    fn mac(&self, socket_address: &SocketAddr, issued: u64) -> Hmac<Sha256> {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        hmac.update(socket_address.to_string().as_bytes());
+        hmac.update(&issued.to_le_bytes());
+        hmac
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([198, 51, 100, 1], port))
+    }
+
+    #[test]
+    fn cookie_verifies_for_the_address_it_was_issued_to() {
+        let validator = AddressValidator::new();
+        let cookie = validator.issue(&address(5000));
+
+        assert!(validator.verify(&address(5000), &cookie));
+        assert!(!validator.verify(&address(5001), &cookie));
+        assert!(!AddressValidator::new().verify(&address(5000), &cookie));
+    }
+
+    #[test]
+    fn tampered_or_truncated_cookies_are_rejected() {
+        let validator = AddressValidator::new();
+        let mut cookie = validator.issue(&address(5000));
+
+        assert!(!validator.verify(&address(5000), &cookie[..cookie.len() - 1]));
+        assert!(!validator.verify(&address(5000), &[]));
+
+        *cookie.last_mut().unwrap() ^= 1;
+        assert!(!validator.verify(&address(5000), &cookie));
+    }
+
+    #[test]
+    fn old_cookies_expire() {
+        let validator = AddressValidator::new();
+        let issued = unix_time() - COOKIE_LIFETIME - 1;
+
+        let mut cookie = Vec::new();
+        write_u64(&mut cookie, issued);
+        cookie.extend(&validator.mac(&address(5000), issued).finalize().into_bytes()[..COOKIE_MAC_LEN]);
+
+        assert!(!validator.verify(&address(5000), &cookie));
+    }
+}
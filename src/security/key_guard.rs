@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// failed guesses allowed before any delay kicks in
+const FREE_ATTEMPTS: u32 = 5;
+const BASE_BLOCK_TIME: f32 = 2.0;
+const MAX_BLOCK_TIME: f32 = 60.0 * 60.0;
+const FAILURE_MEMORY: f32 = 10.0 * 60.0;
+
+struct KeyFailures {
+    count: u32,
+    last_failure: Instant,
+    blocked_until: Instant
+}
+
+// Counts failed session key lookups per ip, every failure past the free attempts
+// doubles how long the ip has to wait before it may try again
+#[derive(Default)]
+pub struct KeyGuard {
+    failures: HashMap<IpAddr, KeyFailures>
+}
+
+impl KeyGuard {
+    // Seconds left until the ip may look up keys again
+    pub fn get_block_time(&self, ip: &IpAddr) -> Option<f32> {
+        let failures = self.failures.get(ip)?;
+        let now = Instant::now();
+
+        if failures.blocked_until > now {
+            Some((failures.blocked_until - now).as_secs_f32())
+        } else {
+            None
+        }
+    }
+
+    pub fn record_failure(&mut self, ip: &IpAddr) {
+        let now = Instant::now();
+
+        let failures = self.failures.entry(*ip).or_insert(KeyFailures {
+            count: 0,
+            last_failure: now,
+            blocked_until: now
+        });
+
+        failures.count += 1;
+        failures.last_failure = now;
+
+        if failures.count > FREE_ATTEMPTS {
+            let exponent = (failures.count - FREE_ATTEMPTS - 1).min(31) as i32;
+            let block_time = (BASE_BLOCK_TIME * 2f32.powi(exponent)).min(MAX_BLOCK_TIME);
+
+            failures.blocked_until = now + Duration::from_secs_f32(block_time);
+
+            tracing::warn!(ip = %ip, seconds = block_time, failed_attempts = failures.count, "Blocking key lookups");
+        }
+    }
+
+    // Forgets ips that stopped guessing a while ago
+    pub fn update(&mut self) {
+        let now = Instant::now();
+
+        self.failures.retain(|_, failures| {
+            failures.blocked_until > now
+                || failures.last_failure.elapsed().as_secs_f32() < FAILURE_MEMORY
+        });
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_is_blocked_after_the_free_attempts() {
+        let mut guard = KeyGuard::default();
+        let ip = IpAddr::from([198, 51, 100, 1]);
+
+        for _ in 0..FREE_ATTEMPTS {
+            guard.record_failure(&ip);
+        }
+
+        assert_eq!(guard.get_block_time(&ip), None);
+
+        guard.record_failure(&ip);
+        assert!(guard.get_block_time(&ip).is_some_and(|seconds| seconds <= BASE_BLOCK_TIME));
+        assert_eq!(guard.get_block_time(&IpAddr::from([198, 51, 100, 2])), None);
+    }
+
+    #[test]
+    fn block_time_doubles_with_every_failure() {
+        let mut guard = KeyGuard::default();
+        let ip = IpAddr::from([198, 51, 100, 1]);
+
+        for _ in 0..FREE_ATTEMPTS + 3 {
+            guard.record_failure(&ip);
+        }
+
+        assert!(guard.get_block_time(&ip).is_some_and(|seconds| seconds > BASE_BLOCK_TIME * 3.0));
+    }
+}
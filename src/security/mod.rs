@@ -0,0 +1,29 @@
+mod cookie;
+pub use cookie::AddressValidator;
+
+mod ban_list;
+pub use ban_list::{BanList, BanTarget};
+
+mod clock;
+pub use clock::unix_time;
+
+mod constant_time;
+pub use constant_time::constant_time_eq;
+
+mod challenge;
+pub use challenge::{generate_nonce, verify_response, NONCE_LEN};
+
+mod key_guard;
+pub use key_guard::KeyGuard;
+
+mod password;
+pub use password::PasswordHash;
+
+mod restore_token;
+pub use restore_token::{RestoreToken, RESTORE_TOKEN_LEN};
+
+mod subnet;
+pub use subnet::mask_ip;
+
+mod ticket;
+pub use ticket::TicketSigner;
\ No newline at end of file
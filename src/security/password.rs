@@ -0,0 +1,68 @@
+use super::constant_time_eq;
+use rand::Rng;
+use sha2::Sha256;
+use serde::{Deserialize, Serialize};
+
+const SALT_LEN: usize = 16;
+const DIGEST_LEN: usize = 32;
+// PBKDF2-HMAC-SHA256 rounds. Online guesses are already rate limited and locked out, this slows offline
+// guessing from a leaked snapshot while a join stays around a few milliseconds on the server thread
+const ROUNDS: u32 = 10_000;
+
+// Sessions never hold the plaintext password, only a salted, deliberately slow digest of it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PasswordHash {
+    salt: [u8; SALT_LEN],
+    // kept with the digest so the cost can be raised without breaking snapshots
+    rounds: u32,
+    digest: Vec<u8>
+}
+
+impl PasswordHash {
+    pub fn new(password: &str) -> PasswordHash {
+        let salt: [u8; SALT_LEN] = rand::thread_rng().gen();
+
+        PasswordHash {
+            salt,
+            rounds: ROUNDS,
+            digest: PasswordHash::digest(&salt, ROUNDS, password)
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        constant_time_eq(&PasswordHash::digest(&self.salt, self.rounds, password), &self.digest)
+    }
+
+    fn digest(salt: &[u8], rounds: u32, password: &str) -> Vec<u8> {
+        pbkdf2::pbkdf2_hmac_array::<Sha256, DIGEST_LEN>(password.as_bytes(), salt, rounds).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_same_password_verifies() {
+        let hash = PasswordHash::new("hunter2");
+
+        assert!(hash.verify("hunter2"));
+        assert!(!hash.verify("hunter3"));
+        assert!(!hash.verify(""));
+    }
+
+    #[test]
+    fn same_password_gets_a_different_salt() {
+        let (a, b) = (PasswordHash::new("hunter2"), PasswordHash::new("hunter2"));
+
+        assert_ne!(a.digest, b.digest);
+    }
+
+    #[test]
+    fn survives_a_snapshot() {
+        let hash = PasswordHash::new("hunter2");
+        let restored: PasswordHash = serde_json::from_str(&serde_json::to_string(&hash).unwrap()).unwrap();
+
+        assert!(restored.verify("hunter2"));
+    }
+}
@@ -0,0 +1,26 @@
+use super::constant_time_eq;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const RESTORE_TOKEN_LEN: usize = 16;
+
+// Lets a host claim its session back after a restart, whatever address it comes back from.
+// Only a digest is kept so a leaked snapshot can't be used to take sessions over
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RestoreToken {
+    digest: Vec<u8>
+}
+
+impl RestoreToken {
+    // The token goes to the host, the RestoreToken stays with the session
+    pub fn generate() -> ([u8; RESTORE_TOKEN_LEN], RestoreToken) {
+        let token: [u8; RESTORE_TOKEN_LEN] = rand::thread_rng().gen();
+
+        (token, RestoreToken { digest: Sha256::digest(token).to_vec() })
+    }
+
+    pub fn verify(&self, token: &[u8]) -> bool {
+        constant_time_eq(&Sha256::digest(token), &self.digest)
+    }
+}
@@ -0,0 +1,17 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Clears everything past the first `prefix` bits, e.g. 10.1.2.3/24 -> 10.1.2.0
+pub fn mask_ip(ip: &IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let prefix = prefix.min(32) as u32;
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(*ip) & mask))
+        },
+        IpAddr::V6(ip) => {
+            let prefix = prefix.min(128) as u32;
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(*ip) & mask))
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+use super::unix_time;
+use crate::packets::{read_string_u8, read_u64, write_string_u8, write_u64};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use std::net::SocketAddr;
+
+const TICKET_LIFETIME: u64 = 60 * 60;
+
+// Tickets let a client reclaim its state from a new address after a NAT rebind.
+// The signed payload is the address the client was known by and the client's ticket
+// nonce, the server replaces the nonce once a ticket is redeemed so each works only once.
+pub struct TicketSigner {
+    secret: [u8; 32]
+}
+
+impl TicketSigner {
+    pub fn new() -> TicketSigner {
+        TicketSigner {
+            secret: rand::thread_rng().gen()
+        }
+    }
+
+    pub fn issue(&self, socket_address: &SocketAddr, nonce: u64) -> Vec<u8> {
+        let mut ticket = Vec::new();
+
+        write_string_u8(&mut ticket, &socket_address.to_string());
+        write_u64(&mut ticket, nonce);
+        write_u64(&mut ticket, unix_time() + TICKET_LIFETIME);
+
+        let signature = self.sign(&ticket);
+        ticket.extend(signature);
+
+        ticket
+    }
+
+    // Returns the address and nonce the ticket was issued with if it is authentic and unexpired
+    pub fn verify(&self, ticket: &[u8]) -> Option<(SocketAddr, u64)> {
+        let mut buf = ticket;
+
+        let socket_address = read_string_u8(&mut buf)?;
+        let nonce = read_u64(&mut buf)?;
+        let expiry = read_u64(&mut buf)?;
+
+        let payload = &ticket[..ticket.len() - buf.len()];
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(&self.secret).ok()?;
+        hmac.update(payload);
+        hmac.verify_slice(buf).ok()?;
+
+        if expiry < unix_time() {
+            return None;
+        }
+
+        Some((socket_address.parse().ok()?, nonce))
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        hmac.update(payload);
+        hmac.finalize().into_bytes().to_vec()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticket_returns_what_it_was_issued_with() {
+        let signer = TicketSigner::new();
+        let address = SocketAddr::from(([198, 51, 100, 1], 5000));
+
+        assert_eq!(signer.verify(&signer.issue(&address, 42)), Some((address, 42)));
+    }
+
+    #[test]
+    fn forged_tickets_are_rejected() {
+        let signer = TicketSigner::new();
+        let mut ticket = signer.issue(&SocketAddr::from(([198, 51, 100, 1], 5000)), 42);
+
+        assert_eq!(TicketSigner::new().verify(&ticket), None);
+        assert_eq!(signer.verify(&ticket[..ticket.len() - 1]), None);
+
+        // the address is the first thing signed
+        ticket[1] ^= 1;
+        assert_eq!(signer.verify(&ticket), None);
+    }
+}
@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod server;
+pub use server::{Server, ShutdownHandle};
+
+mod server_builder;
+pub use server_builder::{ServerBuilder, ConfigLoader, EventHandler};
+
+mod server_event;
+pub use server_event::ServerEvent;
\ No newline at end of file
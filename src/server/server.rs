@@ -0,0 +1,1767 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{UdpSocket, TcpListener, SocketAddr};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{ConfigLoader, EventHandler, ServerBuilder, ServerEvent};
+use crate::packets::{PacketShipper, PacketReciever, ClientPacket, ServerPacket, ErrorReason, SessionListing, Metadata, negotiate_version,
+    CAPABILITY_RELAY, CAPABILITY_ECHO_PORT, CAPABILITY_RANKED, build_server_packet, build_relay_packet, build_echo_packet, build_retry_packet, data_packet_len, fit_start_members, paginate_listings};
+use crate::admin::{AdminCommand, AdminReply, ClientInfo, SessionInfo, Stats};
+use crate::config::{ServerConfig, ConfigError, Build, BuildList, BuildStatus};
+use crate::logging::LogHandle;
+use crate::matchmaking::{MatchQueue, RatingQueue, is_compatible};
+use crate::metrics::Metrics;
+use crate::ratelimit::{RateLimiter, LimitKind};
+use crate::relay::RelayChannel;
+use crate::snapshot::{SessionSnapshot, Snapshot};
+use crate::security::{AddressValidator, BanList, KeyGuard, PasswordHash, RestoreToken, TicketSigner, generate_nonce, verify_response, NONCE_LEN, RESTORE_TOKEN_LEN};
+use tracing::{debug, error, info, warn};
+use crate::threads::{create_listening_thread, create_clock_thread, create_echo_thread, create_console_thread, create_metrics_thread, create_admin_thread, ThreadGroup, ThreadMessage};
+#[cfg(unix)]
+use crate::threads::create_signal_thread;
+
+const MAX_LOCAL_ADDRESSES: usize = 4;
+const PUNCH_LEAD_TIME: f32 = 1.0;
+const PUNCH_ACK_TIMEOUT: f32 = 5.0;
+const MIN_PLAYERS: u8 = 2;
+const MAX_PLAYERS: u8 = 8;
+const MAX_KEY_ATTEMPTS: usize = 16;
+const RATE_LIMIT_REPORT_INTERVAL: f32 = 60.0;
+
+struct Session {
+    key: String,
+    build_group: String,
+    metadata: Metadata,
+    creation_time: Instant,
+    password: Option<PasswordHash>,
+    // failed join attempts the session may see before joiners are asked for the password, 0 always asks.
+    // only a successful join resets the count
+    password_after: u8,
+    // wrong keys anywhere, each could have been a guess at this one, and wrong passwords for it
+    failed_attempts: u32,
+    max_players: u8,
+    // joined clients, the host is not included
+    members: Vec<SocketAddr>,
+    // checked against the token the host presents after a restart
+    restore_token: RestoreToken,
+    // brought back from a snapshot, the host's next Create keeps the key
+    restored: bool
+}
+
+struct Client {
+    reciever: PacketReciever,
+    shipper: PacketShipper,
+    session: Option<Session>,
+    local_addrs: Vec<SocketAddr>,
+    peer: Option<SocketAddr>,
+    protocol_version: Option<u16>,
+    capabilities: u32,
+    // single use challenge, cleared once the client answered it
+    nonce: Option<[u8; NONCE_LEN]>,
+    // the authenticated client build
+    build: Option<Build>,
+    // signed into every ticket, replaced when one is redeemed so a ticket can't be used twice
+    ticket_nonce: u64
+}
+
+// Every member needs their Start packet before they can be told when to punch
+struct PendingPunch {
+    members: Vec<(SocketAddr, u32)>,
+    creation_time: Instant
+}
+
+// The matchmaker, built with `Server::builder` and run with `Server::poll`
+pub struct Server {
+    config: ServerConfig,
+    clients: HashMap<SocketAddr, Client>,
+    sessions: HashMap<String, SocketAddr>,
+    pending_punches: Vec<PendingPunch>,
+    match_queue: MatchQueue,
+    rating_queue: RatingQueue,
+    relays: HashMap<u32, RelayChannel>,
+    next_relay_id: u32,
+    ticket_signer: TicketSigner,
+    address_validator: AddressValidator,
+    rate_limiter: RateLimiter,
+    key_guard: KeyGuard,
+    ban_list: BanList,
+    last_rate_limit_report: (Instant, String),
+    builds: BuildList,
+    log_handle: Option<LogHandle>,
+    // set once the server started saying goodbye
+    shutdown_time: Option<Instant>,
+    // sessions from the last snapshot whose hosts haven't come back yet
+    restored_sessions: HashMap<String, SessionSnapshot>,
+    restore_deadline: Instant,
+    last_snapshot_time: Instant,
+    config_loader: Option<ConfigLoader>,
+    event_handlers: Vec<EventHandler>,
+    // shared with the listening and metrics threads
+    metrics: Arc<Metrics>,
+    console: bool,
+    signals: bool,
+    tx: mpsc::Sender<ThreadMessage>,
+    // taken by poll, it can only run once
+    rx: Option<mpsc::Receiver<ThreadMessage>>
+}
+
+// Asks a running server to shut down gracefully, from any thread
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    tx: mpsc::Sender<ThreadMessage>
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        // the server may have stopped already
+        let _ = self.tx.send(ThreadMessage::Shutdown);
+    }
+}
+
+impl Server {
+
+    //
+    // static fn
+    //
+
+    pub fn builder(config: ServerConfig) -> ServerBuilder {
+        ServerBuilder::new(config)
+    }
+
+    // Files the builder wasn't handed are read from the paths in the config
+    pub(super) fn new(builder: ServerBuilder) -> Result<Server, ConfigError> {
+        let config = builder.config;
+        config.validate()?;
+
+        let builds = match builder.builds {
+            Some(builds) => builds,
+            None => BuildList::load(&config.files.hashes)?
+        };
+
+        let ban_list = BanList::load(&config.files.bans);
+        let snapshot = Snapshot::load(&config.snapshot.path);
+        let (tx, rx) = mpsc::channel();
+
+        let mut server = Server { 
+            rate_limiter: RateLimiter::new(config.rate_limit.clone()),
+            config,
+            clients: HashMap::new(),
+            sessions: HashMap::new(),
+            pending_punches: Vec::new(),
+            match_queue: MatchQueue::default(),
+            rating_queue: RatingQueue::default(),
+            relays: HashMap::new(),
+            next_relay_id: 0,
+            ticket_signer: TicketSigner::new(),
+            address_validator: AddressValidator::new(),
+            key_guard: KeyGuard::default(),
+            ban_list,
+            last_rate_limit_report: (Instant::now(), String::new()),
+            builds,
+            log_handle: builder.log_handle,
+            shutdown_time: None,
+            restored_sessions: HashMap::new(),
+            restore_deadline: Instant::now(),
+            last_snapshot_time: Instant::now(),
+            config_loader: builder.config_loader,
+            event_handlers: builder.event_handlers,
+            metrics: Arc::new(Metrics::new()),
+            console: builder.console,
+            signals: builder.signals,
+            tx,
+            rx: Some(rx)
+        };
+
+        server.set_snapshot(snapshot);
+
+        Ok(server)
+    }
+
+    pub fn poll(server: &mut Server) -> Result<(), Box<dyn std::error::Error>> {
+        let network = server.config.network.clone();
+        let socket = UdpSocket::bind((network.bind_address, network.port))?;
+
+        let rx = server.rx.take().ok_or("The server is already running")?;
+        let tx = server.tx.clone();
+
+        // stopped and joined on every way out of poll, which releases their sockets
+        let mut threads = ThreadGroup::default();
+        threads.add_task(create_listening_thread(tx.clone(), socket.try_clone()?, server.metrics.clone(), threads.get_stop_signal()));
+        threads.add(create_clock_thread(tx.clone(), network.tick_rate, threads.get_stop_signal()));
+
+        // blocks on stdin and holds nothing, it ends with the next line typed after the server stopped
+        if server.console {
+            create_console_thread(tx.clone());
+        }
+
+        #[cfg(unix)]
+        if server.signals {
+            if let Some(thread) = create_signal_thread(tx.clone(), threads.get_stop_signal()) {
+                threads.add(thread);
+            }
+        }
+
+        if let Some(echo_port) = network.echo_port {
+            threads.add(create_echo_thread(UdpSocket::bind((network.bind_address, echo_port))?, threads.get_stop_signal())?);
+        }
+
+        let metrics_config = &server.config.metrics;
+
+        if metrics_config.enabled {
+            // the matchmaker is still useful without metrics, don't abort over them
+            match TcpListener::bind((metrics_config.bind_address, metrics_config.port)) {
+                Ok(listener) => {
+                    info!(address = %listener.local_addr()?, "Serving metrics");
+                    threads.add(create_metrics_thread(listener, server.metrics.clone(), threads.get_stop_signal())?);
+                },
+                Err(e) => error!(port = metrics_config.port, error = %e, "Failed to bind metrics socket")
+            }
+        }
+
+        let admin_config = &server.config.admin;
+
+        if admin_config.enabled {
+            match TcpListener::bind((admin_config.bind_address, admin_config.port)) {
+                Ok(listener) => {
+                    info!(address = %listener.local_addr()?, "Accepting admin connections");
+                    threads.add(create_admin_thread(tx.clone(), listener, threads.get_stop_signal())?);
+                },
+                Err(e) => error!(port = admin_config.port, error = %e, "Failed to bind admin socket")
+            }
+        }
+
+        info!(address = %socket.local_addr()?, "Server started");
+
+        let mut time;
+        let mut last_ping_pong = Instant::now();
+        // unacknowledged packets are sent again every tick
+        let retry_delay = Duration::from_secs_f64(1.0 / network.tick_rate);
+
+        loop {
+            match rx.recv()? {
+                ThreadMessage::Tick(started) => {
+                    started();
+
+                    time = Instant::now();
+
+                    // kick silent clients
+                    let mut kick_list = Vec::new();
+
+                    let timeouts = &server.config.timeouts;
+
+                    for(socket_address, client) in &mut server.clients {
+                        let last_message_time = client.reciever.get_last_message_time();
+
+                        if last_message_time.elapsed().as_secs_f32() > timeouts.max_silence_duration {
+                            kick_list.push(*socket_address);
+                            continue;
+                        }
+
+                        // start ping-pong, not while draining or the pings would keep it from finishing
+                        if server.shutdown_time.is_none() && last_ping_pong.elapsed().as_secs_f32() >= timeouts.ping_pong_rate {
+                            client.shipper.send(&socket, &ServerPacket::Ping);
+                            last_ping_pong = time;
+                        }
+
+                       client.shipper.resend_unacknowledged_packets(&socket, retry_delay);
+                    }
+
+                    for socket_address in kick_list {
+                        info!(client = %socket_address, "Dropping host due to silence");
+                        server.close_client(&socket, &socket_address);
+                    }
+
+                    server.update_match_queue(&socket);
+                    server.update_rating_queue(&socket);
+                    server.update_pending_punches(&socket);
+                    server.update_relays(&socket);
+                    server.update_rate_limits();
+                    server.key_guard.update();
+                    server.ban_list.update();
+                    server.update_metrics();
+                    server.update_snapshot();
+
+                    if server.is_drained() {
+                        break;
+                    }
+                }
+                ThreadMessage::Command(line) => {
+                    println!("{}", server.handle_command(&socket, &line));
+                }
+                ThreadMessage::AdminCommand { line, reply } => {
+                    // the connection may be gone already
+                    let _ = reply.send(server.handle_command(&socket, &line));
+                }
+                ThreadMessage::Shutdown => {
+                    if server.shutdown_time.is_some() {
+                        warn!("Stopping without waiting for the remaining acks");
+                        break;
+                    }
+
+                    server.begin_shutdown(&socket);
+                }
+                ThreadMessage::Reload => {
+                    if let Err(e) = server.reload(&socket) {
+                        error!(error = %e, "Reload failed, keeping the current configuration");
+                    }
+                }
+                ThreadMessage::ClientPacket {
+                    socket_address,
+                    id,
+                    packet,
+                    len
+                } => {
+                    if server.rate_limiter.is_ignored(&socket_address.ip()) {
+                        // offenders get no replies at all until their ignore runs out
+                        continue;
+                    }
+
+                    if let ClientPacket::RelayData { channel, data } = &packet {
+                        // relayed game traffic is unreliable, it is never acked or sorted
+                        server.forward_relay_data(&socket, socket_address, *channel, data);
+                    } else if let ClientPacket::Echo = &packet {
+                        // stateless, answer without tracking a client
+                        let reply = build_echo_packet(&socket_address);
+
+                        // never answer with more than we were sent, or we'd amplify spoofed traffic
+                        if reply.len() <= len {
+                            let _ = socket.send_to(&reply, socket_address);
+                        }
+                    } else if server.has_client(&socket_address) {
+                        let reciever = &mut server.clients.get_mut(&socket_address).unwrap().reciever;
+                        
+                        if let Some(data) = reciever.sort_packets(&socket, id, packet) {
+                            server.handle_packet(&socket, socket_address, id, data)
+                        }
+                    } else if server.shutdown_time.is_some() {
+                        // no new connections while shutting down
+                        continue;
+                    } else if matches!(&packet, ClientPacket::Hello { protocol_version, .. } if negotiate_version(*protocol_version).is_none()) {
+                        // outdated clients may not know the cookie round trip, tell them to update before asking for one.
+                        // the address is unproven, so banned and rate limited senders get nothing back
+                        if !server.rate_limiter.check(LimitKind::Connect, &socket_address.ip()) || server.ban_list.is_ip_banned(&socket_address.ip()) {
+                            continue;
+                        }
+
+                        let reply = [
+                            ServerPacket::Error{ id, reason: ErrorReason::UnsupportedVersion, message: "Client is outdated, please update" },
+                            ServerPacket::Error{ id, reason: ErrorReason::UnsupportedVersion, message: "" }
+                        ];
+
+                        // never answer with more than we were sent, the oldest clients didn't pad their Hello and get nothing
+                        if let Some(reply) = reply.iter().find(|reply| data_packet_len(reply) <= len) {
+                            PacketShipper::new(socket_address, server.metrics.clone()).send(&socket, reply);
+                        }
+                    } else if let Some(cookie) = packet.get_cookie() {
+                        if !server.address_validator.verify(&socket_address, cookie) {
+                            // unproven address, nothing is allocated until it echoes a cookie back
+                            let reply = build_retry_packet(&server.address_validator.issue(&socket_address));
+
+                            if reply.len() <= len {
+                                let _ = socket.send_to(&reply, socket_address);
+                            }
+
+                            continue;
+                        }
+
+                        if !server.rate_limiter.check(LimitKind::Connect, &socket_address.ip()) {
+                            continue;
+                        }
+
+                        if server.ban_list.is_ip_banned(&socket_address.ip()) {
+                            // the address is proven so an answer can't be reflected at someone else
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::Banned, message: "You are banned from this server" };
+                            PacketShipper::new(socket_address, server.metrics.clone()).send(&socket, &reply);
+                            continue;
+                        }
+
+                        // new connection
+                        let mut client = Client { 
+                            reciever: PacketReciever::new(socket_address),
+                            shipper: PacketShipper::new(socket_address, server.metrics.clone()),
+                            session: None,
+                            local_addrs: Vec::new(),
+                            peer: None,
+                            protocol_version: None,
+                            capabilities: 0,
+                            nonce: None,
+                            build: None,
+                            ticket_nonce: rand::thread_rng().gen()
+                        };
+    
+                        let reciever = &mut client.reciever;
+
+                        if let Some(data) = reciever.sort_packets(&socket, id, packet) {
+                            server.clients.insert(socket_address, client);
+
+                            debug!(client = %socket_address, id, "First data packet from new client");
+                            server.handle_packet(&socket, socket_address, id, data)
+                        }
+                    }
+                    // anything else from an unknown address is dropped without a reply
+                }
+            }
+        }
+
+        let unacknowledged: usize = server.clients.values().map(|client| client.shipper.get_unacknowledged_count()).sum();
+        let metrics = &server.metrics;
+
+        info!(
+            clients = server.clients.len(),
+            unacknowledged,
+            drain_seconds = server.shutdown_time.map_or(0.0, |shutdown_time| shutdown_time.elapsed().as_secs_f32()),
+            sessions_created = metrics.sessions_created.get(),
+            matches_made = metrics.matches_made.get(),
+            "Shutdown complete"
+        );
+
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, socket: &UdpSocket, socket_address: SocketAddr, id: u32, packet: ClientPacket) {
+        if self.has_client(&socket_address) {
+            if packet.requires_handshake() && self.clients.get(&socket_address).unwrap().protocol_version.is_none() {
+                let reply = ServerPacket::Error{ id, reason: ErrorReason::HandshakeRequired, message: "Send Hello before any other request" };
+                self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                return;
+            }
+
+            let build_group = self.clients.get(&socket_address).unwrap().build.as_ref().map(|build| build.group.clone());
+
+            if packet.requires_auth() && build_group.is_none() {
+                let reply = ServerPacket::Error{ id, reason: ErrorReason::HandshakeRequired, message: "Authenticate before any other request" };
+                self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                return;
+            }
+
+            let build_group = build_group.unwrap_or_default();
+
+            if self.shutdown_time.is_some() && matches!(packet, ClientPacket::Create { .. } | ClientPacket::Restore { .. } | ClientPacket::Join { .. }) {
+                let reply = ServerPacket::Error{ id, reason: ErrorReason::ServerShutdown, message: "Server is shutting down" };
+                self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                return;
+            }
+
+            match packet {
+                ClientPacket::Pong => {},
+                ClientPacket::Ack { id } => {
+                    self.clients.get_mut(&socket_address).unwrap().shipper.acknowledge(id);
+                },
+                ClientPacket::Hello { protocol_version, capabilities, .. } => {
+                    let server_capabilities = self.get_capabilities();
+                    let client = self.clients.get_mut(&socket_address).unwrap();
+
+                    if let Some(version) = negotiate_version(protocol_version) {
+                        let nonce = generate_nonce();
+
+                        client.protocol_version = Some(version);
+                        client.capabilities = capabilities & server_capabilities;
+                        client.nonce = Some(nonce);
+
+                        let reply = ServerPacket::Welcome{ protocol_version: version, capabilities: client.capabilities, nonce: &nonce };
+                        client.shipper.send(socket, &reply);
+                    } else {
+                        info!(client = %socket_address, protocol_version, "Client uses unsupported protocol version");
+
+                        let message = format!("Protocol version {} is no longer supported, please update", protocol_version);
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::UnsupportedVersion, message: &message };
+                        client.shipper.send(socket, &reply);
+                    }
+                },
+                ClientPacket::Auth { mac } => {
+                    let nonce = self.clients.get_mut(&socket_address).unwrap().nonce.take();
+                    let build = nonce.and_then(|nonce| self.authenticate(&nonce, &mac)).cloned();
+
+                    if build.as_ref().is_some_and(|build| self.ban_list.is_hash_banned(&build.hash)) {
+                        warn!(client = %socket_address, "Client authenticated with a banned build");
+                        self.kick_banned_client(socket, &socket_address);
+                        return;
+                    }
+
+                    let client = self.clients.get_mut(&socket_address).unwrap();
+
+                    if let Some(build) = build {
+                        match build.get_status() {
+                            BuildStatus::Blocked => {
+                                info!(client = %socket_address, build = %build.name, "Client uses blocked build");
+
+                                let message = format!("{} is no longer supported, please update", build.name);
+                                let reply = ServerPacket::Error{ id, reason: ErrorReason::BuildBlocked, message: &message };
+                                client.shipper.send(socket, &reply);
+                                return;
+                            },
+                            BuildStatus::Deprecated => {
+                                let message = format!("{} is deprecated, please update soon", build.name);
+                                let reply = ServerPacket::Error{ id, reason: ErrorReason::BuildDeprecated, message: &message };
+                                client.shipper.send(socket, &reply);
+                            },
+                            BuildStatus::Allowed => {}
+                        }
+
+                        client.build = Some(build);
+                        client.shipper.send(socket, &ServerPacket::Auth{ success: true });
+                    } else {
+                        warn!(client = %socket_address, "Client failed authentication");
+
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::AuthenticationFailed, message: "Client build is not supported" };
+                        client.shipper.send(socket, &reply);
+                    }
+                },
+                ClientPacket::Reconnect { ticket, .. } => {
+                    let old_address = self.ticket_signer
+                        .verify(&ticket)
+                        .filter(|(old_address, nonce)| self.clients.get(old_address).is_some_and(|client| client.ticket_nonce == *nonce))
+                        .map(|(old_address, _)| old_address);
+
+                    match old_address {
+                        Some(old_address) if old_address != socket_address => {
+                            info!(client = %socket_address, old_address = %old_address, "Client reconnected");
+                            self.migrate_client(&old_address, &socket_address);
+
+                            // every ticket issued so far is spent, only the one sent back now is valid
+                            self.clients.get_mut(&socket_address).unwrap().ticket_nonce = rand::thread_rng().gen();
+
+                            let ticket = self.issue_ticket(&socket_address);
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &ServerPacket::Reconnect{ ticket: &ticket });
+                        },
+                        _ => {
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::TicketRejected, message: "Ticket is invalid or expired" };
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        }
+                    }
+                },
+                ClientPacket::Create { password, password_after, max_players, metadata, local_addrs } => {
+                    if !self.rate_limiter.check(LimitKind::Create, &socket_address.ip()) {
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::RateLimited, message: "Too many sessions created, try again later" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        return;
+                    }
+
+                    if !metadata.is_within_limits() {
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::InvalidMetadata, message: "Session metadata is too large" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        return;
+                    }
+
+                    self.set_local_addresses(&socket_address, &local_addrs);
+                    self.leave_session(socket, &socket_address);
+
+                    if let Some((key, restore_token)) = self.create_session(&socket_address, &build_group, &password, password_after, max_players, metadata) {
+                        let ticket = self.issue_ticket(&socket_address);
+                        let reply = ServerPacket::Create{ session_key: &key, ticket: &ticket, restore_token: &restore_token };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                    } else {
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::SessionCreateFailed, message: "Session failed to create" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                    }
+                },
+                ClientPacket::Restore { session_key, token } => {
+                    if !self.restore_session(socket, &socket_address, &build_group, &session_key, &token) {
+                        info!(client = %socket_address, session = %session_key, "Client failed to restore a session");
+
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::RestoreRejected, message: "Session can no longer be restored" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                    }
+                },
+                ClientPacket::Join { session_key, password, criteria, local_addrs } => {
+                    if !self.rate_limiter.check(LimitKind::Join, &socket_address.ip()) {
+                        self.metrics.join_failures.increment();
+
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::RateLimited, message: "Too many join attempts, try again later" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        return;
+                    }
+
+                    self.set_local_addresses(&socket_address, &local_addrs);
+
+                    if session_key.is_empty() && RatingQueue::is_ranked(&criteria) {
+                        // ranked players are paired with each other rather than with hosts
+                        self.drop_client_session(socket, &socket_address);
+
+                        if !self.rating_queue.push(socket_address, build_group, criteria) {
+                            self.metrics.join_failures.increment();
+
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::PlayerIdRequired, message: "Ranked play requires a player id" };
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        }
+                    } else if session_key.is_empty() {
+                        // wait in the queue until a compatible session shows up, a client is only ever in one queue
+                        self.rating_queue.remove_client(&socket_address);
+                        self.match_queue.push(socket_address, build_group, criteria);
+                        self.update_match_queue(socket);
+                    } else {
+                        let ip = socket_address.ip();
+
+                        if self.key_guard.get_block_time(&ip).is_some() {
+                            self.metrics.join_failures.increment();
+
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::TooManyAttempts, message: "Too many failed join attempts, try again later" };
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                            return;
+                        }
+
+                        if let Some(client_addr) = self.get_socket_addr_from_session(&session_key, &socket_address) {
+                            if !self.is_session_compatible(&client_addr, &build_group) {
+                                self.metrics.join_failures.increment();
+
+                                let reply = ServerPacket::Error{ id, reason: ErrorReason::IncompatibleBuild, message: "The host is playing on an incompatible version" };
+                                self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                                return;
+                            }
+
+                            if !self.verify_session_password(&client_addr, &password) {
+                                warn!(client = %socket_address, host = %client_addr, "Client supplied an incorrect session password");
+                                self.key_guard.record_failure(&ip);
+                                self.record_session_failure(&client_addr);
+                                self.metrics.join_failures.increment();
+
+                                let reply = ServerPacket::Error{ id, reason: ErrorReason::InvalidPassword, message: "Session password is incorrect" };
+                                self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                                return;
+                            }
+
+                            self.join_session(socket, &socket_address, &client_addr);
+                        } else {
+                            self.key_guard.record_failure(&ip);
+                            self.record_key_failure();
+                            self.metrics.join_failures.increment();
+
+                            self.clients
+                            .get_mut(&socket_address)
+                            .unwrap()
+                            .shipper
+                            .send(socket, &ServerPacket::Join{ client_addr: None, local_addrs: &[], ticket: &[], success: false });
+                        }
+                    }
+                },
+                ClientPacket::Metadata { metadata } => {
+                    let session = self.clients
+                        .get_mut(&socket_address)
+                        .unwrap()
+                        .session
+                        .as_mut();
+
+                    match session {
+                        Some(session) if metadata.is_within_limits() => {
+                            session.metadata = metadata;
+                        },
+                        Some(_) => {
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::InvalidMetadata, message: "Session metadata is too large" };
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        },
+                        None => {
+                            let reply = ServerPacket::Error{ id, reason: ErrorReason::InvalidMetadata, message: "No session to update" };
+                            self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                        }
+                    }
+                },
+                ClientPacket::List { filters, page } => {
+                    let (total, listings) = self.list_sessions(&socket_address, &build_group, &filters, page);
+                    let reply = ServerPacket::List { page, total, sessions: &listings };
+                    self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                },
+                ClientPacket::RelayRequest => {
+                    if !self.open_relay(socket, &socket_address) {
+                        let reply = ServerPacket::Error{ id, reason: ErrorReason::RelayUnavailable, message: "Relay is unavailable" };
+                        self.clients.get_mut(&socket_address).unwrap().shipper.send(socket, &reply);
+                    }
+                },
+                ClientPacket::RelayData { .. } | ClientPacket::Echo => {
+                    // handled before sorting in poll()
+                },
+                ClientPacket::Close => {
+                    self.drop_client_session(socket, &socket_address);
+                }
+            }
+        }
+    }
+
+    //
+    // non mut fn
+    //
+
+    fn generate_key(&self) -> String {
+        let alphabet: Vec<char> = self.config.sessions.key_alphabet.chars().collect();
+        let mut rng = rand::thread_rng();
+
+        (0..self.config.sessions.key_length)
+            .map(|_| alphabet[rng.gen_range(0, alphabet.len())])
+            .collect()
+    }
+
+    fn has_key(&self, key: &str) -> bool {
+        // keys of hosts that may still come back are reserved
+        self.sessions.contains_key(key) || self.restored_sessions.contains_key(key)
+    }
+
+    fn has_client(&self, socket_address: &SocketAddr) -> bool {
+        self.clients.contains_key(socket_address)
+    }
+
+    fn has_session(&self, socket_address: &SocketAddr) -> bool {
+        let result = self
+        .sessions
+        .iter()
+        .find_map(|(key, &val)| if val == *socket_address { Some(key) } else { None });
+
+        result.is_some()
+    }
+
+    // The hash never crosses the wire, find the build whose secret signed the nonce
+    fn authenticate(&self, nonce: &[u8], mac: &[u8]) -> Option<&Build> {
+        self.builds
+            .iter()
+            .find(|build| verify_response(&build.hash, nonce, mac))
+    }
+
+    fn get_socket_addr_from_session(&self, key: &str, exclude_socket: &SocketAddr) -> Option<SocketAddr> {
+        if let Some(socket) = self.sessions.get(key) {
+            if exclude_socket != socket {
+                return Some(*socket)
+            }
+        }
+
+        None
+    }
+
+    // Picks the longest waiting public session that satisfies the queued joiner
+    fn get_socket_addr_from_open_session(&self, queue_index: usize) -> Option<SocketAddr> {
+        let joiner = self.match_queue.get(queue_index)?;
+        let wait_time = joiner.queue_time.elapsed().as_secs_f32();
+
+        self.sessions
+            .values()
+            .filter_map(|client_socket| {
+                let session = self.clients.get(client_socket).unwrap().session.as_ref().unwrap();
+
+                let compatible = session.password.is_none()
+                    && session.build_group == joiner.build_group
+                    && is_compatible(&joiner.criteria, &session.metadata, wait_time)
+                    && *client_socket != joiner.socket_address;
+
+                if compatible { Some((client_socket, session.creation_time)) } else { None }
+            })
+            .min_by_key(|(_, creation_time)| *creation_time)
+            .map(|(client_socket, _)| *client_socket)
+    }
+
+    // Finds the host of the session this client joined as a member
+    fn get_joined_session_host(&self, socket_address: &SocketAddr) -> Option<SocketAddr> {
+        self.sessions
+            .values()
+            .find(|host_addr| {
+                self.clients
+                    .get(host_addr)
+                    .and_then(|client| client.session.as_ref())
+                    .map(|session| session.members.contains(socket_address))
+                    .unwrap_or(false)
+            })
+            .cloned()
+    }
+
+    // Host first, followed by members in the order they joined
+    fn get_session_roster(&self, host_addr: &SocketAddr) -> Vec<SocketAddr> {
+        let mut roster = vec![*host_addr];
+
+        if let Some(session) = self.clients.get(host_addr).and_then(|client| client.session.as_ref()) {
+            roster.extend(session.members.iter().cloned());
+        }
+
+        roster
+    }
+
+    // Public sessions built with the same client, oldest first so pages stay stable.
+    // Returns the number of pages along with the requested one.
+    fn list_sessions(&self, exclude_socket: &SocketAddr, build_group: &str, filters: &Metadata, page: u16) -> (u16, Vec<SessionListing>) {
+        let mut sessions: Vec<&Session> = self.sessions
+            .values()
+            .filter(|host_addr| *host_addr != exclude_socket)
+            .filter_map(|host_addr| self.clients.get(host_addr).and_then(|client| client.session.as_ref()))
+            .filter(|session| {
+                session.password.is_none()
+                && session.build_group == build_group
+                && session.metadata.matches(filters)
+            })
+            .collect();
+
+        sessions.sort_by_key(|session| session.creation_time);
+
+        let listings = sessions
+            .iter()
+            .map(|session| SessionListing {
+                session_key: session.key.clone(),
+                age: session.creation_time.elapsed().as_secs() as u32,
+                players: (session.members.len() + 1) as u8,
+                max_players: session.max_players,
+                metadata: session.metadata.clone()
+            })
+            .collect();
+
+        // metadata sizes vary, a page holds as many listings as fit in one datagram
+        let pages = paginate_listings(listings, self.config.sessions.sessions_per_page);
+        let total = pages.len().min(u16::MAX.into()) as u16;
+
+        (total, pages.into_iter().nth(page.into()).unwrap_or_default())
+    }
+
+    fn is_session_compatible(&self, host_addr: &SocketAddr, build_group: &str) -> bool {
+        self.clients
+            .get(host_addr)
+            .and_then(|client| client.session.as_ref())
+            .is_some_and(|session| session.build_group == build_group)
+    }
+
+    // Hosts may waive the password until their session has seen enough failed attempts
+    fn verify_session_password(&self, host_addr: &SocketAddr, password: &str) -> bool {
+        let session = match self.clients.get(host_addr).and_then(|client| client.session.as_ref()) {
+            Some(session) => session,
+            None => return true
+        };
+
+        match &session.password {
+            Some(hash) if session.failed_attempts >= u32::from(session.password_after) => hash.verify(password),
+            _ => true
+        }
+    }
+
+    fn issue_ticket(&self, socket_address: &SocketAddr) -> Vec<u8> {
+        let nonce = self.clients.get(socket_address).map_or(0, |client| client.ticket_nonce);
+        self.ticket_signer.issue(socket_address, nonce)
+    }
+
+    pub fn get_shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle { tx: self.tx.clone() }
+    }
+
+    fn get_capabilities(&self) -> u32 {
+        let mut capabilities = CAPABILITY_RANKED;
+
+        if self.config.relay.enabled {
+            capabilities |= CAPABILITY_RELAY;
+        }
+
+        if self.config.network.echo_port.is_some() {
+            capabilities |= CAPABILITY_ECHO_PORT;
+        }
+
+        capabilities
+    }
+
+    fn unix_time_millis(offset: f32) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        (now.as_secs_f64() * 1000.0 + f64::from(offset) * 1000.0) as u64
+    }
+
+    fn get_client_infos(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self.clients
+            .iter()
+            .map(|(socket_address, client)| ClientInfo {
+                address: socket_address.to_string(),
+                build: client.build.as_ref().map(|build| build.name.clone()),
+                protocol_version: client.protocol_version,
+                session: client.session.as_ref().map(|session| session.key.clone()),
+                idle_seconds: client.reciever.get_last_message_time().elapsed().as_secs_f32()
+            })
+            .collect();
+
+        clients.sort_by(|a, b| a.address.cmp(&b.address));
+        clients
+    }
+
+    fn get_session_infos(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<(&SocketAddr, &Session)> = self.sessions
+            .values()
+            .filter_map(|host_addr| self.clients.get(host_addr).and_then(|client| client.session.as_ref()).map(|session| (host_addr, session)))
+            .collect();
+
+        sessions.sort_by_key(|(_, session)| session.creation_time);
+
+        sessions
+            .into_iter()
+            .map(|(host_addr, session)| SessionInfo {
+                key: session.key.clone(),
+                host: host_addr.to_string(),
+                build_group: session.build_group.clone(),
+                players: session.members.len() + 1,
+                max_players: session.max_players,
+                password_protected: session.password.is_some(),
+                age_seconds: session.creation_time.elapsed().as_secs()
+            })
+            .collect()
+    }
+
+    // Done once every client acked the goodbye or the drain time ran out
+    fn is_drained(&self) -> bool {
+        match self.shutdown_time {
+            Some(shutdown_time) => {
+                shutdown_time.elapsed().as_secs_f32() >= self.config.shutdown.drain_time
+                    || self.clients.values().all(|client| client.shipper.get_unacknowledged_count() == 0)
+            },
+            None => false
+        }
+    }
+
+    fn take_snapshot(&self) -> Snapshot {
+        let open_sessions = self.sessions
+            .values()
+            .filter_map(|host_addr| self.clients.get(host_addr).and_then(|client| client.session.as_ref()))
+            .map(|session| SessionSnapshot {
+                key: session.key.clone(),
+                restore_token: session.restore_token.clone(),
+                build_group: session.build_group.clone(),
+                metadata: session.metadata.clone(),
+                password: session.password.clone(),
+                password_after: session.password_after,
+                max_players: session.max_players,
+                created: SessionSnapshot::unix_time_of_age(session.creation_time.elapsed().as_secs())
+            });
+
+        // hosts still within their grace period get another chance after the next restart
+        let waiting_sessions = self.restored_sessions.values().cloned();
+
+        Snapshot {
+            sessions: open_sessions.chain(waiting_sessions).collect()
+        }
+    }
+
+    fn get_stats(&self) -> Stats {
+        let metrics = &self.metrics;
+
+        Stats {
+            clients: self.clients.len(),
+            sessions: self.sessions.len(),
+            relays: self.relays.len(),
+            queued_clients: self.match_queue.len() + self.rating_queue.len(),
+            bans: self.ban_list.iter().count(),
+            sessions_created: metrics.sessions_created.get(),
+            matches_made: metrics.matches_made.get(),
+            join_failures: metrics.join_failures.get(),
+            packets_received: metrics.packets_received.get(),
+            parse_failures: metrics.parse_failures.get(),
+            packets_sent: metrics.packets_sent.get(),
+            packet_resends: metrics.packet_resends.get(),
+            rate_limits: self.rate_limiter.get_counters().to_string()
+        }
+    }
+
+    //
+    // mut fn
+    //
+
+    // Hosts of these sessions get their key back if they present its restore token within the grace period
+    fn set_snapshot(&mut self, snapshot: Snapshot) {
+        self.restore_deadline = Instant::now() + Duration::from_secs_f32(self.config.snapshot.grace_period);
+        self.restored_sessions = snapshot.sessions
+            .into_iter()
+            .map(|session| (session.key.clone(), session))
+            .collect();
+    }
+
+    // The host proves the session was its own with the token from its last Create reply
+    fn restore_session(&mut self, socket: &UdpSocket, socket_address: &SocketAddr, build_group: &str, session_key: &str, token: &[u8]) -> bool {
+        // a different game can't take the session over even with the token
+        let valid = self.restored_sessions
+            .get(session_key)
+            .is_some_and(|snapshot| snapshot.build_group == build_group && snapshot.restore_token.verify(token));
+
+        if !valid {
+            return false;
+        }
+
+        // whatever the host was doing since the restart is replaced by its old session
+        self.drop_client_session(socket, socket_address);
+
+        let snapshot = self.restored_sessions.remove(session_key).unwrap();
+        let age = Duration::from_secs(snapshot.get_age());
+
+        // the token was sent over the wire again, hand out a fresh one
+        let (token, restore_token) = RestoreToken::generate();
+
+        let session = Session {
+            key: snapshot.key.clone(),
+            build_group: snapshot.build_group,
+            metadata: snapshot.metadata,
+            creation_time: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+            password: snapshot.password,
+            password_after: snapshot.password_after,
+            failed_attempts: 0,
+            max_players: snapshot.max_players,
+            members: Vec::new(),
+            restore_token,
+            restored: true
+        };
+
+        self.clients.get_mut(socket_address).unwrap().session = Some(session);
+        self.sessions.insert(snapshot.key.clone(), *socket_address);
+
+        info!(client = %socket_address, session = %snapshot.key, "Session restored");
+        self.emit(ServerEvent::SessionCreated { host: *socket_address, session_key: snapshot.key.clone() });
+
+        let ticket = self.issue_ticket(socket_address);
+        let reply = ServerPacket::Create{ session_key: &snapshot.key, ticket: &ticket, restore_token: &token };
+        self.clients.get_mut(socket_address).unwrap().shipper.send(socket, &reply);
+
+        true
+    }
+
+    // Returns the session key and the token that restores the session after a restart
+    fn create_session(&mut self, socket_address: &SocketAddr, build_group: &str, password: &str, password_after: u8, max_players: u8, metadata: Metadata) -> Option<(String, [u8; RESTORE_TOKEN_LEN])> {
+        let mut result = None;
+
+        // anything below two players means a 1v1 session
+        let max_players = max_players.clamp(MIN_PLAYERS, MAX_PLAYERS);
+
+        let restored = self.clients
+            .get_mut(socket_address)
+            .and_then(|client| client.session.as_mut())
+            .filter(|session| session.restored);
+
+        // the host recreating the session it had before a restart, only the settings change
+        if let Some(session) = restored {
+            session.password = if password.is_empty() { None } else { Some(PasswordHash::new(password)) };
+            session.password_after = password_after;
+            session.max_players = max_players;
+            session.metadata = metadata;
+            session.restored = false;
+
+            let (token, restore_token) = RestoreToken::generate();
+            session.restore_token = restore_token;
+
+            return Some((session.key.clone(), token));
+        }
+
+        if !self.has_session(socket_address) {
+            // bounded so a crowded key space can't stall the server
+            for _ in 0..MAX_KEY_ATTEMPTS {
+                let new_key = self.generate_key();
+
+                if !self.has_key(&new_key) {
+                    let password_protected = !password.is_empty();
+                    let (token, restore_token) = RestoreToken::generate();
+                    let session = Session {
+                        key: new_key.clone(),
+                        build_group: build_group.to_string(),
+                        metadata: metadata.clone(),
+                        creation_time: Instant::now(),
+                        password: if password_protected { Some(PasswordHash::new(password)) } else { None },
+                        password_after,
+                        failed_attempts: 0,
+                        max_players,
+                        members: Vec::new(),
+                        restore_token,
+                        restored: false
+                    };
+
+                    let client = self.clients.get_mut(socket_address).unwrap();
+                    client.session = Some(session);
+                    
+                    self.sessions.insert(new_key.clone(), *socket_address);
+                    self.metrics.sessions_created.increment();
+
+                    info!(client = %socket_address, session = %new_key, password_protected, max_players, "Session created");
+                    self.emit(ServerEvent::SessionCreated { host: *socket_address, session_key: new_key.clone() });
+
+                    result = Some((new_key, token));
+                    break;
+                }
+            }
+        } else {
+            warn!(client = %socket_address, "Session cannot be created because it already exists");
+        }
+
+        result
+    }
+
+    // A key that matched no session could have been a guess at any of them
+    fn record_key_failure(&mut self) {
+        for session in self.clients.values_mut().filter_map(|client| client.session.as_mut()) {
+            session.failed_attempts = session.failed_attempts.saturating_add(1);
+        }
+    }
+
+    fn record_session_failure(&mut self, host_addr: &SocketAddr) {
+        if let Some(session) = self.clients.get_mut(host_addr).and_then(|client| client.session.as_mut()) {
+            session.failed_attempts = session.failed_attempts.saturating_add(1);
+        }
+    }
+
+    fn set_local_addresses(&mut self, socket_address: &SocketAddr, local_addrs: &[String]) {
+        let client = self.clients.get_mut(socket_address).unwrap();
+
+        // unparsable candidates are useless to the peer, skip them
+        client.local_addrs = local_addrs
+            .iter()
+            .filter_map(|addr| addr.parse::<SocketAddr>().ok())
+            .take(MAX_LOCAL_ADDRESSES)
+            .collect();
+    }
+
+    fn join_session(&mut self, socket: &UdpSocket, joiner_addr: &SocketAddr, host_addr: &SocketAddr) {
+        // a client can only be in one session at a time
+        self.drop_client_session(socket, joiner_addr);
+
+        let session = self.clients.get_mut(host_addr).unwrap().session.as_mut().unwrap();
+        session.members.push(*joiner_addr);
+        // someone had the key and the password if it was asked for, the guessing starts over
+        session.failed_attempts = 0;
+
+        let is_full = session.members.len() + 1 >= session.max_players.into();
+        let host_local_addrs = self.clients.get(host_addr).unwrap().local_addrs.clone();
+        let ticket = self.issue_ticket(joiner_addr);
+
+        self.clients
+            .get_mut(joiner_addr)
+            .unwrap()
+            .shipper
+            .send(socket, &ServerPacket::Join{ client_addr: Some(host_addr), local_addrs: &host_local_addrs, ticket: &ticket, success: true });
+
+        self.broadcast_roster(socket, host_addr);
+
+        if is_full {
+            self.start_session(socket, host_addr);
+        }
+    }
+
+    fn broadcast_roster(&mut self, socket: &UdpSocket, host_addr: &SocketAddr) {
+        let roster = self.get_session_roster(host_addr);
+
+        let session = self.clients.get(host_addr).unwrap().session.as_ref().unwrap();
+        let key = session.key.clone();
+        let max_players = session.max_players;
+
+        let reply = ServerPacket::Roster { session_key: &key, max_players, members: &roster };
+
+        for addr in &roster {
+            self.clients.get_mut(addr).unwrap().shipper.send(socket, &reply);
+        }
+    }
+
+    // The session is full, hand every member the addresses of everyone else
+    fn start_session(&mut self, socket: &UdpSocket, host_addr: &SocketAddr) {
+        let roster = self.get_session_roster(host_addr);
+
+        self.start_match(socket, &roster);
+
+        // the session is no longer open for joining
+        if let Some(session) = self.clients.get_mut(host_addr).unwrap().session.take() {
+            self.sessions.remove(&session.key);
+            self.metrics.session_wait_time.observe(session.creation_time.elapsed().as_secs_f64());
+
+            info!(session = %session.key, players = roster.len(), "Session started");
+        }
+    }
+
+    fn start_match(&mut self, socket: &UdpSocket, roster: &[SocketAddr]) {
+        let peers: Vec<(SocketAddr, Vec<SocketAddr>)> = roster
+            .iter()
+            .map(|addr| (*addr, self.clients.get(addr).unwrap().local_addrs.clone()))
+            .collect();
+
+        let mut members = Vec::new();
+        self.metrics.matches_made.increment();
+        self.emit(ServerEvent::MatchMade { members: roster.to_vec() });
+
+        for addr in roster {
+            let mut others: Vec<(SocketAddr, Vec<SocketAddr>)> = peers
+                .iter()
+                .filter(|(peer_addr, _)| peer_addr != addr)
+                .cloned()
+                .collect();
+
+            fit_start_members(&mut others);
+
+            let packet_id = self.clients
+                .get_mut(addr)
+                .unwrap()
+                .shipper
+                .send(socket, &ServerPacket::Start { members: &others });
+
+            members.push((*addr, packet_id));
+        }
+
+        // relays only make sense between a pair
+        if let [first, second] = roster[..] {
+            self.clients.get_mut(&first).unwrap().peer = Some(second);
+            self.clients.get_mut(&second).unwrap().peer = Some(first);
+        }
+
+        self.pending_punches.push(PendingPunch {
+            members,
+            creation_time: Instant::now()
+        });
+    }
+
+    fn update_match_queue(&mut self, socket: &UdpSocket) {
+        let mut index = 0;
+
+        while index < self.match_queue.len() {
+            if let Some(host_addr) = self.get_socket_addr_from_open_session(index) {
+                let joiner = self.match_queue.remove(index);
+                self.join_session(socket, &joiner.socket_address, &host_addr);
+            } else {
+                index += 1;
+            }
+        }
+
+        for socket_address in self.match_queue.expire(self.config.timeouts.queue_time) {
+            self.metrics.join_failures.increment();
+
+            if let Some(client) = self.clients.get_mut(&socket_address) {
+                client.shipper.send(socket, &ServerPacket::Join{ client_addr: None, local_addrs: &[], ticket: &[], success: false });
+            }
+        }
+    }
+
+    fn update_rating_queue(&mut self, socket: &UdpSocket) {
+        for (first, second) in self.rating_queue.find_pairs() {
+            info!(first = %first, second = %second, "Ranked match made");
+
+            // both players are waiting on their join like a joiner matched with a host
+            for (addr, opponent_addr) in &[(first, second), (second, first)] {
+                let opponent_local_addrs = self.clients.get(opponent_addr).unwrap().local_addrs.clone();
+                let ticket = self.issue_ticket(addr);
+                let reply = ServerPacket::Join{ client_addr: Some(opponent_addr), local_addrs: &opponent_local_addrs, ticket: &ticket, success: true };
+
+                self.clients.get_mut(addr).unwrap().shipper.send(socket, &reply);
+            }
+
+            self.start_match(socket, &[first, second]);
+        }
+
+        for socket_address in self.rating_queue.expire(self.config.timeouts.ranked_queue_time) {
+            self.metrics.join_failures.increment();
+
+            if let Some(client) = self.clients.get_mut(&socket_address) {
+                client.shipper.send(socket, &ServerPacket::Join{ client_addr: None, local_addrs: &[], ticket: &[], success: false });
+            }
+        }
+    }
+
+    // Removes a member from the session they joined and tells everyone left
+    fn leave_session(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) {
+        if let Some(host_addr) = self.get_joined_session_host(socket_address) {
+            let session = self.clients.get_mut(&host_addr).unwrap().session.as_mut().unwrap();
+            session.members.retain(|addr| addr != socket_address);
+
+            self.broadcast_roster(socket, &host_addr);
+        }
+    }
+
+    // Once every member acknowledged their Start, schedule a simultaneous punch
+    fn update_pending_punches(&mut self, socket: &UdpSocket) {
+        let clients = &mut self.clients;
+
+        self.pending_punches.retain(|punch| {
+            let mut ready = true;
+
+            for (addr, packet_id) in &punch.members {
+                match clients.get(addr) {
+                    Some(client) => ready &= client.shipper.is_acknowledged(*packet_id),
+                    // one of the peers left, nobody to punch to
+                    None => return false
+                }
+            }
+
+            if !ready {
+                return punch.creation_time.elapsed().as_secs_f32() < PUNCH_ACK_TIMEOUT;
+            }
+
+            let reply = ServerPacket::Punch {
+                server_time: Server::unix_time_millis(0.0),
+                start_time: Server::unix_time_millis(PUNCH_LEAD_TIME)
+            };
+
+            for (addr, _) in &punch.members {
+                clients.get_mut(addr).unwrap().shipper.send(socket, &reply);
+            }
+
+            false
+        });
+    }
+
+    // Allocates a relay between the client and its matched peer, reusing one if it exists
+    fn open_relay(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) -> bool {
+        if !self.config.relay.enabled {
+            return false;
+        }
+
+        let peer_addr = match self.clients.get(socket_address).and_then(|client| client.peer) {
+            Some(peer_addr) if self.has_client(&peer_addr) => peer_addr,
+            _ => return false
+        };
+
+        let existing = self
+            .relays
+            .values()
+            .find(|relay| relay.has_peer(socket_address) && relay.has_peer(&peer_addr))
+            .map(|relay| relay.get_id());
+
+        let channel = match existing {
+            Some(channel) => channel,
+            None => {
+                if self.relays.len() >= self.config.relay.max_relays {
+                    warn!(client = %socket_address, "Relay denied, relay limit reached");
+                    return false;
+                }
+
+                let channel = self.next_relay_id;
+                self.next_relay_id = self.next_relay_id.wrapping_add(1);
+
+                let relay = RelayChannel::new(channel, [*socket_address, peer_addr], self.config.relay.max_bytes_per_second);
+                self.relays.insert(channel, relay);
+                self.metrics.relays_opened.increment();
+
+                info!(channel, first = %socket_address, second = %peer_addr, "Relay opened");
+
+                channel
+            }
+        };
+
+        let reply = ServerPacket::Relay { channel, active: true };
+
+        for addr in &[*socket_address, peer_addr] {
+            self.clients.get_mut(addr).unwrap().shipper.send(socket, &reply);
+        }
+
+        true
+    }
+
+    fn forward_relay_data(&mut self, socket: &UdpSocket, socket_address: SocketAddr, channel: u32, data: &[u8]) {
+        let destination = match self.relays.get_mut(&channel) {
+            Some(relay) => relay.route(&socket_address, data.len()),
+            None => None
+        };
+
+        if let Some(destination) = destination {
+            // relayed traffic keeps the sender alive like any other packet
+            if let Some(client) = self.clients.get_mut(&socket_address) {
+                client.reciever.touch();
+            }
+
+            let _ = socket.send_to(&build_relay_packet(channel, data), destination);
+        }
+    }
+
+    fn update_relays(&mut self, socket: &UdpSocket) {
+        let idle_timeout = self.config.relay.idle_timeout;
+
+        let idle_list: Vec<u32> = self
+            .relays
+            .values()
+            .filter(|relay| relay.get_last_activity().elapsed().as_secs_f32() > idle_timeout)
+            .map(|relay| relay.get_id())
+            .collect();
+
+        for channel in idle_list {
+            info!(channel, "Closing relay due to inactivity");
+            self.close_relay(socket, channel);
+        }
+    }
+
+    fn update_rate_limits(&mut self) {
+        self.rate_limiter.update();
+
+        // counters are cumulative, only report them when something changed
+        let (last_report_time, last_report) = &self.last_rate_limit_report;
+
+        if last_report_time.elapsed().as_secs_f32() >= RATE_LIMIT_REPORT_INTERVAL {
+            let report = self.rate_limiter.get_counters().to_string();
+
+            if report != *last_report {
+                info!(counters = %report, "Rate limits");
+            }
+
+            self.last_rate_limit_report = (Instant::now(), report);
+        }
+    }
+
+    fn update_snapshot(&mut self) {
+        if !self.restored_sessions.is_empty() && Instant::now() >= self.restore_deadline {
+            info!(sessions = self.restored_sessions.len(), "Dropping restored sessions whose hosts didn't come back");
+            self.restored_sessions.clear();
+        }
+
+        if self.last_snapshot_time.elapsed().as_secs_f32() >= self.config.snapshot.interval {
+            self.take_snapshot().save(&self.config.snapshot.path);
+            self.last_snapshot_time = Instant::now();
+        }
+    }
+
+    fn update_metrics(&self) {
+        let metrics = &self.metrics;
+        let rate_limits = self.rate_limiter.get_counters();
+
+        metrics.connects_limited.set(rate_limits.connects_limited);
+        metrics.creates_limited.set(rate_limits.creates_limited);
+        metrics.joins_limited.set(rate_limits.joins_limited);
+        metrics.addresses_ignored.set(rate_limits.addresses_ignored);
+        metrics.packets_ignored.set(rate_limits.packets_ignored);
+
+        metrics.connected_clients.set(self.clients.len());
+        metrics.open_sessions.set(self.sessions.len());
+        metrics.open_relays.set(self.relays.len());
+        metrics.queued_clients.set(self.match_queue.len() + self.rating_queue.len());
+    }
+
+    fn close_relay(&mut self, socket: &UdpSocket, channel: u32) {
+        if let Some(relay) = self.relays.remove(&channel) {
+            let reply = ServerPacket::Relay { channel, active: false };
+
+            for addr in relay.get_peers() {
+                if let Some(client) = self.clients.get_mut(addr) {
+                    client.shipper.send(socket, &reply);
+                }
+            }
+        }
+    }
+
+    // Moves everything known about a client to the address it now talks from
+    fn migrate_client(&mut self, old_address: &SocketAddr, new_address: &SocketAddr) {
+        let mut client = match self.clients.remove(old_address) {
+            Some(client) => client,
+            None => return
+        };
+
+        // the entry created for the unknown address is replaced by the original
+        self.clients.remove(new_address);
+
+        client.shipper.set_socket_address(*new_address);
+        client.reciever.set_socket_address(*new_address);
+        client.reciever.touch();
+
+        let migrate = |addr: &mut SocketAddr| {
+            if addr == old_address {
+                *addr = *new_address;
+            }
+        };
+
+        self.sessions.values_mut().for_each(migrate);
+
+        for other in self.clients.values_mut() {
+            other.peer.iter_mut().for_each(migrate);
+
+            if let Some(session) = other.session.as_mut() {
+                session.members.iter_mut().for_each(migrate);
+            }
+        }
+
+        for punch in &mut self.pending_punches {
+            punch.members.iter_mut().for_each(|(addr, _)| migrate(addr));
+        }
+
+        for relay in self.relays.values_mut() {
+            relay.migrate_peer(old_address, new_address);
+        }
+
+        self.match_queue.migrate_client(old_address, new_address);
+        self.rating_queue.migrate_client(old_address, new_address);
+
+        self.clients.insert(*new_address, client);
+    }
+
+    // Drop the client session only, members of a hosted session are told it closed
+    fn drop_client_session(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) -> bool {
+        self.match_queue.remove_client(socket_address);
+        self.rating_queue.remove_client(socket_address);
+        self.leave_session(socket, socket_address);
+
+        if let Some(client) = self.clients.get_mut(socket_address) {
+            if let Some(session) = client.session.take() {
+                self.sessions.remove(&session.key);
+
+                let reply = ServerPacket::Roster { session_key: &session.key, max_players: session.max_players, members: &[] };
+
+                for addr in &session.members {
+                    if let Some(member) = self.clients.get_mut(addr) {
+                        member.shipper.send(socket, &reply);
+                    }
+                }
+            }
+
+            return true;
+        }
+
+        false
+    }
+
+    // Admin commands from the console or an admin connection, returns the rendered reply
+    fn handle_command(&mut self, socket: &UdpSocket, line: &str) -> String {
+        let (format, command) = AdminCommand::parse(line);
+
+        let reply = match command {
+            Ok(AdminCommand::Clients) => AdminReply::Clients(self.get_client_infos()),
+            Ok(AdminCommand::Sessions) => AdminReply::Sessions(self.get_session_infos()),
+            Ok(AdminCommand::Kick(socket_address)) => {
+                if self.has_client(&socket_address) {
+                    info!(client = %socket_address, "Kicked client");
+                    self.close_client(socket, &socket_address);
+                    AdminReply::Done(format!("Kicked {}", socket_address))
+                } else {
+                    AdminReply::Error(format!("{} is not connected", socket_address))
+                }
+            },
+            Ok(AdminCommand::Close(key)) => {
+                if self.close_session(socket, &key) {
+                    info!(session = %key, "Closed session");
+                    AdminReply::Done(format!("Closed session {}", key))
+                } else {
+                    AdminReply::Error(format!("There is no open session {}", key))
+                }
+            },
+            Ok(AdminCommand::Ban(target, duration)) => {
+                let message = format!("Banned {} {}", target, duration.map_or("permanently".to_string(), |duration| format!("for {}s", duration)));
+
+                info!(target = %target, seconds = ?duration, "Banned");
+                self.ban_list.add(target, duration);
+                self.kick_banned_clients(socket);
+                AdminReply::Done(message)
+            },
+            Ok(AdminCommand::Unban(target)) => {
+                if self.ban_list.remove(&target) {
+                    info!(target = %target, "Unbanned");
+                    AdminReply::Done(format!("Unbanned {}", target))
+                } else {
+                    AdminReply::Error(format!("{} is not banned", target))
+                }
+            },
+            Ok(AdminCommand::Bans) => AdminReply::Bans(self.ban_list.iter().map(|ban| ban.to_string()).collect()),
+            Ok(AdminCommand::Reload) => match self.reload(socket) {
+                Ok(()) => AdminReply::Done("Reloaded".to_string()),
+                Err(e) => {
+                    error!(error = %e, "Reload failed, keeping the current configuration");
+                    AdminReply::Error(format!("Reload failed, keeping the current configuration: {}", e))
+                }
+            },
+            Ok(AdminCommand::Stats) => AdminReply::Stats(self.get_stats()),
+            Err(message) => AdminReply::Error(message)
+        };
+
+        reply.render(format)
+    }
+
+    // Reads the config file and client hashes again, nothing changes unless all of it is valid.
+    // Connected clients and their sessions are kept as they are.
+    // Without a config loader only the files the current config names are read again.
+    fn reload(&mut self, socket: &UdpSocket) -> Result<(), ConfigError> {
+        let mut config = match &self.config_loader {
+            Some(load_config) => load_config()?,
+            None => self.config.clone()
+        };
+
+        let builds = BuildList::load(&config.files.hashes)?;
+
+        for build in builds.iter() {
+            match self.builds.get(&build.hash) {
+                None => info!(build = %build.name, "Added build"),
+                Some(old) if old != build => info!(build = %build.name, "Updated build"),
+                _ => {}
+            }
+        }
+
+        for build in self.builds.iter().filter(|build| builds.get(&build.hash).is_none()) {
+            info!(build = %build.name, "Removed build");
+        }
+
+        self.builds = builds;
+
+        // sockets and the clock are already running
+        if config.network != self.config.network {
+            warn!("Changes to [network] take effect after a restart");
+            config.network = self.config.network.clone();
+        }
+
+        if config.metrics != self.config.metrics {
+            warn!("Changes to [metrics] take effect after a restart");
+            config.metrics = self.config.metrics.clone();
+        }
+
+        if config.admin != self.config.admin {
+            warn!("Changes to [admin] take effect after a restart");
+            config.admin = self.config.admin.clone();
+        }
+
+        let changed: Vec<&str> = [
+            ("timeouts", config.timeouts != self.config.timeouts),
+            ("sessions", config.sessions != self.config.sessions),
+            ("files", config.files != self.config.files),
+            ("relay", config.relay != self.config.relay),
+            ("rate_limit", config.rate_limit != self.config.rate_limit),
+            ("logging", config.logging != self.config.logging),
+            ("shutdown", config.shutdown != self.config.shutdown),
+            ("snapshot", config.snapshot != self.config.snapshot)
+        ]
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(section, _)| *section)
+        .collect();
+
+        if !changed.is_empty() {
+            info!(sections = %changed.join(", "), "Reloaded configuration");
+        }
+
+        // the output format is picked once at startup
+        if config.logging.json != self.config.logging.json {
+            warn!("Changes to logging.json take effect after a restart");
+        }
+
+        if let Some(log_handle) = &self.log_handle {
+            log_handle.set_level(&config.logging.level);
+        }
+
+        self.rate_limiter.set_config(config.rate_limit.clone());
+        self.ban_list = BanList::load(&config.files.bans);
+        self.config = config;
+
+        self.kick_banned_clients(socket);
+        self.kick_blocked_clients(socket);
+
+        Ok(())
+    }
+
+    // Drops clients whose build was blocked, expired or removed from the build list,
+    // the others keep a copy of their build as it is listed now
+    fn kick_blocked_clients(&mut self, socket: &UdpSocket) {
+        let mut blocked = Vec::new();
+
+        for (socket_address, client) in &mut self.clients {
+            let old_build = match &client.build {
+                Some(build) => build,
+                None => continue
+            };
+
+            match self.builds.get(&old_build.hash) {
+                Some(build) if build.get_status() != BuildStatus::Blocked => client.build = Some(build.clone()),
+                _ => blocked.push((*socket_address, old_build.name.clone()))
+            }
+        }
+
+        for (socket_address, name) in blocked {
+            info!(client = %socket_address, build = %name, "Dropping client with blocked build");
+
+            if let Some(client) = self.clients.get_mut(&socket_address) {
+                let message = format!("{} is no longer supported, please update", name);
+                let reply = ServerPacket::Error{ id: 0, reason: ErrorReason::BuildBlocked, message: &message };
+                client.shipper.send(socket, &reply);
+            }
+
+            self.drop_client(socket, &socket_address);
+        }
+    }
+
+    // Drops connected clients a freshly added ban applies to
+    fn kick_banned_clients(&mut self, socket: &UdpSocket) {
+        let banned: Vec<SocketAddr> = self.clients
+            .iter()
+            .filter(|(socket_address, client)| {
+                self.ban_list.is_ip_banned(&socket_address.ip())
+                    || client.build.as_ref().is_some_and(|build| self.ban_list.is_hash_banned(&build.hash))
+            })
+            .map(|(socket_address, _)| *socket_address)
+            .collect();
+
+        for socket_address in banned {
+            self.kick_banned_client(socket, &socket_address);
+        }
+    }
+
+    fn kick_banned_client(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) {
+        info!(client = %socket_address, "Dropping banned client");
+
+        if let Some(client) = self.clients.get_mut(socket_address) {
+            let reply = ServerPacket::Error{ id: 0, reason: ErrorReason::Banned, message: "You are banned from this server" };
+            client.shipper.send(socket, &reply);
+        }
+
+        self.drop_client(socket, socket_address);
+    }
+
+    fn emit(&mut self, event: ServerEvent) {
+        for handler in &mut self.event_handlers {
+            handler(&event);
+        }
+    }
+
+    // Stops matchmaking and tells every client why, the main loop ends once they acked
+    fn begin_shutdown(&mut self, socket: &UdpSocket) {
+        let retry_after = self.config.shutdown.retry_after;
+
+        let message = if retry_after > 0 {
+            format!("Server restarting, retry in {} seconds", retry_after)
+        } else {
+            "Server shutting down".to_string()
+        };
+
+        info!(clients = self.clients.len(), drain_time = self.config.shutdown.drain_time, "Shutting down");
+
+        self.shutdown_time = Some(Instant::now());
+        self.take_snapshot().save(&self.config.snapshot.path);
+
+        for (socket_address, client) in &mut self.clients {
+            self.match_queue.remove_client(socket_address);
+            self.rating_queue.remove_client(socket_address);
+
+            client.shipper.send(socket, &ServerPacket::Error{ id: 0, reason: ErrorReason::ServerShutdown, message: &message });
+            client.shipper.send(socket, &ServerPacket::Close);
+        }
+
+        // the clients stay around only to ack the goodbye
+        let dropped: Vec<SocketAddr> = self.clients.keys().copied().collect();
+
+        for client in dropped {
+            self.emit(ServerEvent::ClientDropped { client });
+        }
+    }
+
+    // Tells the client it was disconnected, it won't wait for an ack
+    fn close_client(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) {
+        let buf = build_server_packet(&ServerPacket::Close);
+        let _ = socket.send_to(&buf, socket_address);
+
+        self.drop_client(socket, socket_address);
+    }
+
+    // Ends an open session, the host and every member get an empty roster
+    fn close_session(&mut self, socket: &UdpSocket, key: &str) -> bool {
+        let host_addr = match self.sessions.get(key) {
+            Some(host_addr) => *host_addr,
+            None => return false
+        };
+
+        let max_players = self.clients
+            .get(&host_addr)
+            .and_then(|client| client.session.as_ref())
+            .map_or(0, |session| session.max_players);
+
+        self.drop_client_session(socket, &host_addr);
+
+        if let Some(client) = self.clients.get_mut(&host_addr) {
+            client.shipper.send(socket, &ServerPacket::Roster { session_key: key, max_players, members: &[] });
+        }
+
+        true
+    }
+
+    // Drop the client entirely including associated resources
+    fn drop_client(&mut self, socket: &UdpSocket, socket_address: &SocketAddr) -> bool {
+        if self.drop_client_session(socket, socket_address) {
+            self.clients.remove(socket_address);
+
+            // the other side is told its relay is gone
+            let channels: Vec<u32> = self.relays.iter().filter(|(_, relay)| relay.has_peer(socket_address)).map(|(channel, _)| *channel).collect();
+
+            for channel in channels {
+                self.close_relay(socket, channel);
+            }
+
+            // every client was reported dropped when the shutdown began
+            if self.shutdown_time.is_none() {
+                self.emit(ServerEvent::ClientDropped { client: *socket_address });
+            }
+
+            return true;
+        }
+
+        false
+    }
+}
\ No newline at end of file
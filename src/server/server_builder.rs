@@ -0,0 +1,83 @@
+use super::{Server, ServerEvent};
+use crate::config::{BuildList, ConfigError, ServerConfig};
+use crate::logging::LogHandle;
+
+// Produces a fresh config whenever the server is asked to reload
+pub type ConfigLoader = Box<dyn Fn() -> Result<ServerConfig, ConfigError> + Send>;
+pub type EventHandler = Box<dyn FnMut(&ServerEvent) + Send>;
+
+/// Everything besides the config is optional, e.g.
+///
+/// ```
+/// # use matchmaker::config::{ConfigError, ServerConfig};
+/// # use matchmaker::Server;
+/// # fn main() -> Result<(), ConfigError> {
+/// # let mut config = ServerConfig::default();
+/// # config.network.port = 3000;
+/// # config.files.hashes = concat!(env!("CARGO_MANIFEST_DIR"), "/hashes.toml").to_string();
+/// let server = Server::builder(config).on_event(|event| println!("{:?}", event)).build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    pub(super) config: ServerConfig,
+    pub(super) builds: Option<BuildList>,
+    pub(super) log_handle: Option<LogHandle>,
+    pub(super) config_loader: Option<ConfigLoader>,
+    pub(super) event_handlers: Vec<EventHandler>,
+    pub(super) console: bool,
+    pub(super) signals: bool
+}
+
+impl ServerBuilder {
+    pub fn new(config: ServerConfig) -> ServerBuilder {
+        ServerBuilder {
+            config,
+            builds: None,
+            log_handle: None,
+            config_loader: None,
+            event_handlers: Vec::new(),
+            console: false,
+            signals: false
+        }
+    }
+
+    // Instead of reading them from `files.hashes`
+    pub fn builds(mut self, builds: BuildList) -> ServerBuilder {
+        self.builds = Some(builds);
+        self
+    }
+
+    // Lets a reload change the log level
+    pub fn log_handle(mut self, log_handle: LogHandle) -> ServerBuilder {
+        self.log_handle = Some(log_handle);
+        self
+    }
+
+    pub fn config_loader(mut self, loader: impl Fn() -> Result<ServerConfig, ConfigError> + Send + 'static) -> ServerBuilder {
+        self.config_loader = Some(Box::new(loader));
+        self
+    }
+
+    // Called on the polling thread, keep handlers quick
+    pub fn on_event(mut self, handler: impl FnMut(&ServerEvent) + Send + 'static) -> ServerBuilder {
+        self.event_handlers.push(Box::new(handler));
+        self
+    }
+
+    // Read admin commands from stdin
+    pub fn console(mut self, enabled: bool) -> ServerBuilder {
+        self.console = enabled;
+        self
+    }
+
+    // Reload on SIGHUP and shut down on SIGINT or SIGTERM, only on unix
+    pub fn signals(mut self, enabled: bool) -> ServerBuilder {
+        self.signals = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<Server, ConfigError> {
+        Server::new(self)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+use std::net::SocketAddr;
+
+// Handed to the callbacks registered with `ServerBuilder::on_event`
+#[derive(Clone, Debug)]
+pub enum ServerEvent {
+    SessionCreated {
+        host: SocketAddr,
+        session_key: String
+    },
+    // a full session or a ranked pair started, host first
+    MatchMade {
+        members: Vec<SocketAddr>
+    },
+    // timed out, kicked, banned or shut down
+    ClientDropped {
+        client: SocketAddr
+    }
+}
\ No newline at end of file
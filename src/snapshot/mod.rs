@@ -0,0 +1,5 @@
+mod snapshot_config;
+pub use snapshot_config::SnapshotConfig;
+
+mod session_snapshot;
+pub use session_snapshot::{SessionSnapshot, Snapshot};
\ No newline at end of file
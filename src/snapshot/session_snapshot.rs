@@ -0,0 +1,80 @@
+use crate::packets::Metadata;
+use crate::security::{unix_time, PasswordHash, RestoreToken};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// An open session as it was when the snapshot was taken, members have to join again
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub key: String,
+    // the host presents the matching token to get the session back
+    pub restore_token: RestoreToken,
+    pub build_group: String,
+    pub metadata: Metadata,
+    pub password: Option<PasswordHash>,
+    pub password_after: u8,
+    pub max_players: u8,
+    // unix seconds
+    pub created: u64
+}
+
+impl SessionSnapshot {
+    pub fn get_age(&self) -> u64 {
+        unix_time().saturating_sub(self.created)
+    }
+
+    pub fn unix_time_of_age(age: u64) -> u64 {
+        unix_time().saturating_sub(age)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub sessions: Vec<SessionSnapshot>
+}
+
+impl Snapshot {
+    // A missing or unreadable snapshot restores nothing
+    pub fn load(path: &str) -> Snapshot {
+        if path.is_empty() {
+            return Snapshot::default();
+        }
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Snapshot::default()
+        };
+
+        match serde_json::from_str::<Snapshot>(&contents) {
+            Ok(snapshot) => {
+                tracing::info!(path, sessions = snapshot.sessions.len(), "Loaded snapshot");
+                snapshot
+            },
+            Err(e) => {
+                tracing::error!(path, error = %e, "Ignoring malformed snapshot");
+                Snapshot::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        if path.is_empty() {
+            return;
+        }
+
+        let contents = match serde_json::to_string(self) {
+            Ok(contents) => contents,
+            Err(e) => {
+                tracing::error!(path, error = %e, "Failed to serialize snapshot");
+                return;
+            }
+        };
+
+        // written next to the old one first so a crash mid write can't leave half a snapshot
+        let temp_path = format!("{}.tmp", path);
+
+        if let Err(e) = fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, path)) {
+            tracing::error!(path, error = %e, "Failed to save snapshot");
+        }
+    }
+}
\ No newline at end of file
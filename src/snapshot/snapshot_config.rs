@@ -0,0 +1,22 @@
+use serde::Deserialize;
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    // empty disables snapshots
+    pub path: String,
+    // seconds between snapshots, one is also written on shutdown
+    pub interval: f32,
+    // seconds after startup a host has to come back and keep its session key
+    pub grace_period: f32
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> SnapshotConfig {
+        SnapshotConfig {
+            path: String::new(),
+            interval: 30.0,
+            grace_period: 120.0
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,50 @@
+use crate::threads::{accept_until_stopped, StopSignal, ThreadMessage};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+// Accepts admin connections, each one sends a line per command and gets the reply back,
+// e.g. `echo "json clients" | nc 127.0.0.1 9465`
+pub fn create_admin_thread(tx: mpsc::Sender<ThreadMessage>, listener: TcpListener, stop_signal: StopSignal) -> std::io::Result<JoinHandle<()>> {
+    listener.set_nonblocking(true)?;
+
+    Ok(std::thread::spawn(move || {
+        accept_until_stopped(&listener, &stop_signal, |stream| {
+            let tx = tx.clone();
+
+            // a connection may stay open, don't let it block the next one
+            std::thread::spawn(move || {
+                if let Err(e) = serve_connection(tx, stream) {
+                    tracing::debug!(error = %e, "Admin connection closed");
+                }
+            });
+        });
+    }))
+}
+
+fn serve_connection(tx: mpsc::Sender<ThreadMessage>, stream: TcpStream) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+
+        if tx.send(ThreadMessage::AdminCommand { line, reply: reply_tx }).is_err() {
+            break;
+        }
+
+        match reply_rx.recv() {
+            Ok(reply) => writeln!(writer, "{}", reply)?,
+            // the server is shutting down
+            Err(_) => break
+        }
+    }
+
+    Ok(())
+}
\ No newline at end of file
@@ -1,22 +1,21 @@
-use crate::threads::ThreadMessage;
+use crate::threads::{StopSignal, ThreadMessage};
 use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
+use std::thread::JoinHandle;
 
-pub const TICK_RATE: f64 = 20.0;
-
-pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>) {
-  let target = std::time::Duration::from_secs_f64(1.0 / TICK_RATE);
+pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>, tick_rate: f64, stop_signal: StopSignal) -> JoinHandle<()> {
+  let target = std::time::Duration::from_secs_f64(1.0 / tick_rate);
   let behind_counter = Arc::new(AtomicU8::new(0));
 
-  std::thread::spawn(move || loop {
+  std::thread::spawn(move || while !stop_signal.is_stopped() {
     std::thread::sleep(target);
 
     let behind_count = behind_counter.fetch_add(1, Ordering::Relaxed);
 
     if behind_count > 1 {
       behind_counter.fetch_sub(1, Ordering::Relaxed);
-      println!("Server running behind, skipping tick");
+      tracing::warn!("Server running behind, skipping tick");
       continue;
     }
 
@@ -25,6 +24,9 @@ pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>) {
       counter_rc_copy.fetch_sub(1, Ordering::Relaxed);
     });
 
-    tx.send(ThreadMessage::Tick(start_callback)).unwrap();
-  });
+    // the server stopped polling
+    if tx.send(ThreadMessage::Tick(start_callback)).is_err() {
+      break;
+    }
+  })
 }
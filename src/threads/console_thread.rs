@@ -0,0 +1,21 @@
+use crate::threads::ThreadMessage;
+use std::io::BufRead;
+use std::sync::mpsc;
+
+// Forwards admin commands typed into the server console, e.g. `ban 1.2.3.0/24 3600` or `json clients`
+pub fn create_console_thread(tx: mpsc::Sender<ThreadMessage>) {
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break
+            };
+
+            if tx.send(ThreadMessage::Command(line)).is_err() {
+                break;
+            }
+        }
+    });
+}
\ No newline at end of file
@@ -0,0 +1,29 @@
+use crate::packets::{parse_client_packet, build_echo_packet, ClientPacket};
+use crate::threads::{StopSignal, STOP_POLL_INTERVAL};
+use std::net::UdpSocket;
+use std::thread::JoinHandle;
+
+// Answers address echo requests on a secondary port so clients can compare
+// the mapping their NAT assigns per destination
+pub fn create_echo_thread(socket: UdpSocket, stop_signal: StopSignal) -> std::io::Result<JoinHandle<()>> {
+    socket.set_read_timeout(Some(STOP_POLL_INTERVAL))?;
+
+    Ok(std::thread::spawn(move || while !stop_signal.is_stopped() {
+        let mut buf = [0; 64];
+
+        let (number_of_bytes, src_addr) = match socket.recv_from(&mut buf) {
+            Ok(result) => result,
+            // don't crash if there's an error...
+            Err(_) => continue
+        };
+
+        if let Some((_, ClientPacket::Echo)) = parse_client_packet(&buf[..number_of_bytes]) {
+            let reply = build_echo_packet(&src_addr);
+
+            // never answer with more than we were sent
+            if reply.len() <= number_of_bytes {
+                let _ = socket.send_to(&reply, src_addr);
+            }
+        }
+    }))
+}
\ No newline at end of file
@@ -1,18 +1,20 @@
+use crate::metrics::Metrics;
 use crate::packets::parse_client_packet;
-use crate::threads::ThreadMessage;
+use crate::threads::{StopSignal, ThreadMessage, STOP_POLL_INTERVAL};
 use std::net::UdpSocket;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 
-pub fn create_listening_thread(tx: mpsc::Sender<ThreadMessage>, socket: UdpSocket) {
+pub fn create_listening_thread(tx: mpsc::Sender<ThreadMessage>, socket: UdpSocket, metrics: Arc<Metrics>, stop_signal: StopSignal) -> async_std::task::JoinHandle<()> {
     let async_socket = async_std::net::UdpSocket::from(socket);
-    async_std::task::spawn(listen_loop(tx, async_socket));
+    async_std::task::spawn(listen_loop(tx, async_socket, metrics, stop_signal))
 }
 
-async fn listen_loop(tx: mpsc::Sender<ThreadMessage>, async_socket: async_std::net::UdpSocket) {
-    loop {
+async fn listen_loop(tx: mpsc::Sender<ThreadMessage>, async_socket: async_std::net::UdpSocket, metrics: Arc<Metrics>, stop_signal: StopSignal) {
+    while !stop_signal.is_stopped() {
         let mut buf = vec![0; 1024];
 
-        let wrapped_packet = async_socket.recv_from(&mut buf).await;
+        // wakes up now and then to check whether the server stopped
+        let wrapped_packet = async_std::io::timeout(STOP_POLL_INTERVAL, async_socket.recv_from(&mut buf)).await;
 
         if wrapped_packet.is_err() {
             // don't crash if there's an error...
@@ -21,17 +23,23 @@ async fn listen_loop(tx: mpsc::Sender<ThreadMessage>, async_socket: async_std::n
 
         let (number_of_bytes, src_addr) = wrapped_packet.unwrap();
         let data = &buf[..number_of_bytes];
+        metrics.packets_received.increment();
 
         if let Some((id, packet)) = parse_client_packet(data) {
-            tx.send(ThreadMessage::ClientPacket {
+            let message = ThreadMessage::ClientPacket {
                 socket_address: src_addr,
                 id,
-                packet
-            })
-            .unwrap();
+                packet,
+                len: number_of_bytes
+            };
+
+            // the server stopped polling
+            if tx.send(message).is_err() {
+                break;
+            }
         } else {
-            println!("Receive unknown packet from {}", src_addr);
-            println!("{:?}", data);
+            metrics.parse_failures.increment();
+            tracing::debug!(client = %src_addr, bytes = ?data, "Received unknown packet");
         }
     }
 }
\ No newline at end of file
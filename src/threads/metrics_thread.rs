@@ -0,0 +1,45 @@
+use crate::metrics::Metrics;
+use crate::threads::{accept_until_stopped, StopSignal};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Serves the metrics over http for scrapers, one request per connection
+pub fn create_metrics_thread(listener: TcpListener, metrics: Arc<Metrics>, stop_signal: StopSignal) -> std::io::Result<JoinHandle<()>> {
+    listener.set_nonblocking(true)?;
+
+    Ok(std::thread::spawn(move || {
+        accept_until_stopped(&listener, &stop_signal, |stream| {
+            if let Err(e) = respond(stream, &metrics) {
+                tracing::debug!(error = %e, "Metrics request failed");
+            }
+        });
+    }))
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    // a stalled scraper must not hold up the next one for long
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut buf = [0; 1024];
+    let number_of_bytes = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..number_of_bytes]);
+
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "Metrics are served at /metrics\n".to_string())
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
\ No newline at end of file
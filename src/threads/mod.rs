@@ -1,8 +1,28 @@
 mod thread_message;
 pub use thread_message::ThreadMessage;
 
-pub mod clock_thread;
+mod thread_group;
+pub use thread_group::{accept_until_stopped, StopSignal, ThreadGroup, STOP_POLL_INTERVAL};
+
+mod clock_thread;
 pub use clock_thread::create_clock_thread;
 
 mod listening_thread;
-pub use listening_thread::create_listening_thread;
\ No newline at end of file
+pub use listening_thread::create_listening_thread;
+
+mod console_thread;
+pub use console_thread::create_console_thread;
+
+#[cfg(unix)]
+mod signal_thread;
+#[cfg(unix)]
+pub use signal_thread::create_signal_thread;
+
+mod echo_thread;
+pub use echo_thread::create_echo_thread;
+
+mod metrics_thread;
+pub use metrics_thread::create_metrics_thread;
+
+mod admin_thread;
+pub use admin_thread::create_admin_thread;
\ No newline at end of file
@@ -0,0 +1,35 @@
+use crate::threads::{StopSignal, ThreadMessage, STOP_POLL_INTERVAL};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+// SIGHUP asks for the config and client hashes to be read again,
+// SIGINT and SIGTERM for clients to be told before the server stops
+pub fn create_signal_thread(tx: mpsc::Sender<ThreadMessage>, stop_signal: StopSignal) -> Option<JoinHandle<()>> {
+    let mut signals = match Signals::new([SIGHUP, SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to listen for signals, reload from the console instead");
+            return None;
+        }
+    };
+
+    // polled rather than waited on so the thread ends once the server stops
+    Some(std::thread::spawn(move || {
+        while !stop_signal.is_stopped() {
+            for signal in signals.pending() {
+                let message = match signal {
+                    SIGHUP => ThreadMessage::Reload,
+                    _ => ThreadMessage::Shutdown
+                };
+
+                if tx.send(message).is_err() {
+                    return;
+                }
+            }
+
+            std::thread::sleep(STOP_POLL_INTERVAL);
+        }
+    }))
+}
\ No newline at end of file
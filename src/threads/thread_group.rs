@@ -0,0 +1,76 @@
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// how long a thread blocked on a socket may take to notice it should stop
+pub const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// The threads a running server started. Dropping the group stops and joins them,
+// so every port they held is free again by the time poll returns
+#[derive(Default)]
+pub struct ThreadGroup {
+    stop_signal: StopSignal,
+    threads: Vec<JoinHandle<()>>,
+    tasks: Vec<async_std::task::JoinHandle<()>>
+}
+
+impl ThreadGroup {
+    pub fn get_stop_signal(&self) -> StopSignal {
+        self.stop_signal.clone()
+    }
+
+    pub fn add(&mut self, thread: JoinHandle<()>) {
+        self.threads.push(thread);
+    }
+
+    pub fn add_task(&mut self, task: async_std::task::JoinHandle<()>) {
+        self.tasks.push(task);
+    }
+}
+
+impl Drop for ThreadGroup {
+    fn drop(&mut self) {
+        self.stop_signal.stop();
+
+        for task in self.tasks.drain(..) {
+            async_std::task::block_on(task);
+        }
+
+        for thread in self.threads.drain(..) {
+            // a thread that panicked has nothing left to release
+            let _ = thread.join();
+        }
+    }
+}
+
+// Hands accepted connections to `handle` until the server stops, the listener has to be non-blocking
+pub fn accept_until_stopped(listener: &TcpListener, stop_signal: &StopSignal, mut handle: impl FnMut(TcpStream)) {
+    while !stop_signal.is_stopped() {
+        match listener.accept() {
+            // accepted sockets inherit non-blocking mode on some platforms
+            Ok((stream, _)) => match stream.set_nonblocking(false) {
+                Ok(()) => handle(stream),
+                Err(e) => tracing::debug!(error = %e, "Dropping connection")
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(STOP_POLL_INTERVAL),
+            // don't crash if there's an error...
+            Err(_) => continue
+        }
+    }
+}
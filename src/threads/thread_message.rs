@@ -2,9 +2,19 @@ use crate::packets::{ClientPacket};
 
 pub enum ThreadMessage {
     Tick(Box<dyn FnOnce() + Send>),
+    Command(String),
+    // like Command, but the rendered reply goes back to the admin connection
+    AdminCommand {
+        line: String,
+        reply: std::sync::mpsc::Sender<String>
+    },
+    Reload,
+    // a second one stops without waiting for clients to ack
+    Shutdown,
     ClientPacket {
         socket_address: std::net::SocketAddr,
         id: u32,
-        packet: ClientPacket
+        packet: ClientPacket,
+        len: usize
     }
 }
\ No newline at end of file
@@ -0,0 +1,349 @@
+// Shared by the integration tests, each test binary uses a different part of it
+#![allow(dead_code)]
+
+use hmac::{Hmac, Mac};
+use matchmaker::config::ServerConfig;
+use matchmaker::packets::{read_bytes_u8, read_string_u8, read_u16, read_u32, write_bytes_u8, write_metadata, write_string_u8, write_u16, write_u32, Metadata, PROTOCOL_VERSION};
+use matchmaker::{Server, ShutdownHandle};
+use sha2::Sha256;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// client packet headers
+pub const ACK: u16 = 1;
+pub const CREATE: u16 = 2;
+pub const JOIN: u16 = 3;
+pub const ERROR: u16 = 5;
+pub const START: u16 = 11;
+pub const RECONNECT: u16 = 16;
+pub const HELLO: u16 = 14;
+pub const AUTH: u16 = 15;
+pub const RESTORE: u16 = 18;
+
+// build secret listed in the repository's hashes.toml
+const CLIENT_HASH: &str = "ABCDEF";
+const MIN_HANDSHAKE_LEN: usize = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// A fresh directory for the files one test writes
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("matchmaker-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+pub fn free_port() -> u16 {
+    UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port()
+}
+
+// Loopback only, without metrics and with every file kept in `dir`
+pub fn test_config(dir: &Path) -> ServerConfig {
+    let mut config = ServerConfig::default();
+
+    config.network.bind_address = Ipv4Addr::LOCALHOST.into();
+    config.network.port = free_port();
+    config.files.hashes = concat!(env!("CARGO_MANIFEST_DIR"), "/hashes.toml").to_string();
+    config.files.bans = dir.join("bans.txt").to_string_lossy().into_owned();
+    config.metrics.enabled = false;
+    // nobody acks the goodbye while the test waits for the server to stop
+    config.shutdown.drain_time = 0.0;
+
+    config
+}
+
+// Turns the admin port on, commands are then sent with `admin_command`
+pub fn enable_admin(config: &mut ServerConfig) {
+    config.admin.enabled = true;
+    config.admin.port = free_port();
+}
+
+// Runs one command on the admin port and returns the first line of the reply
+pub fn admin_command(config: &ServerConfig, line: &str) -> String {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, config.admin.port)).unwrap();
+    writeln!(stream, "{}", line).unwrap();
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).unwrap();
+
+    reply
+}
+
+pub struct TestServer {
+    pub address: SocketAddr,
+    shutdown_handle: ShutdownHandle,
+    thread: JoinHandle<()>
+}
+
+impl TestServer {
+    pub fn start(config: ServerConfig) -> TestServer {
+        TestServer::start_with(config, |builder| builder)
+    }
+
+    // `customize` gets the builder before the server is built, e.g. to register event handlers
+    pub fn start_with(config: ServerConfig, customize: impl FnOnce(matchmaker::ServerBuilder) -> matchmaker::ServerBuilder + Send + 'static) -> TestServer {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, config.network.port));
+        let (tx, rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut server = customize(Server::builder(config)).build().unwrap();
+            tx.send(server.get_shutdown_handle()).unwrap();
+
+            Server::poll(&mut server).unwrap();
+        });
+
+        let server = TestServer {
+            address,
+            shutdown_handle: rx.recv().unwrap(),
+            thread
+        };
+
+        // a cookie request is answered without any state, so it tells when the socket is bound
+        let mut probe = TestClient::unauthenticated(address);
+        probe.request_cookie();
+
+        server
+    }
+
+    // Shuts down gracefully, which also writes the snapshot
+    pub fn stop(self) {
+        self.shutdown_handle.shutdown();
+        self.thread.join().unwrap();
+    }
+}
+
+// Speaks just enough of the protocol to authenticate, host and join
+pub struct TestClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    next_id: u32
+}
+
+impl TestClient {
+    pub fn connect(server: SocketAddr) -> TestClient {
+        let mut client = TestClient::unauthenticated(server);
+        client.authenticate();
+        client
+    }
+
+    pub fn unauthenticated(server: SocketAddr) -> TestClient {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+        TestClient {
+            socket,
+            server,
+            next_id: 0
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.socket.local_addr().unwrap()
+    }
+
+    pub fn send(&mut self, header: u16, payload: &[u8]) {
+        let mut data = Vec::new();
+
+        write_u32(&mut data, self.next_id);
+        write_u16(&mut data, header);
+        data.extend(payload);
+
+        if header == HELLO {
+            data.resize(data.len().max(MIN_HANDSHAKE_LEN), 0);
+        }
+
+        self.socket.send_to(&data, self.server).unwrap();
+        self.next_id += 1;
+    }
+
+    pub fn create(&mut self, max_players: u8) {
+        self.create_with_password(max_players, "", 0);
+    }
+
+    pub fn create_with_password(&mut self, max_players: u8, password: &str, password_after: u8) {
+        let mut payload = Vec::new();
+
+        write_string_u8(&mut payload, password);
+        payload.push(password_after);
+        payload.push(max_players);
+        write_metadata(&mut payload, &Metadata::default());
+        payload.push(0);
+
+        self.send(CREATE, &payload);
+    }
+
+    pub fn join(&mut self, session_key: &str) {
+        self.join_with_password(session_key, "");
+    }
+
+    pub fn join_with_password(&mut self, session_key: &str, password: &str) {
+        let mut payload = Vec::new();
+
+        write_string_u8(&mut payload, session_key);
+        write_string_u8(&mut payload, password);
+        write_metadata(&mut payload, &Metadata::default());
+        payload.push(0);
+
+        self.send(JOIN, &payload);
+    }
+
+    pub fn restore(&mut self, session_key: &str, token: &[u8]) {
+        let mut payload = Vec::new();
+
+        write_string_u8(&mut payload, session_key);
+        write_bytes_u8(&mut payload, token);
+
+        self.send(RESTORE, &payload);
+    }
+
+    // Returns the body of the next data packet with this header, acking everything on the way
+    pub fn wait_for(&mut self, header: u16) -> Vec<u8> {
+        let deadline = Instant::now() + TIMEOUT;
+
+        while Instant::now() < deadline {
+            if let Some((packet_header, body)) = self.recv_data() {
+                if packet_header == header {
+                    return body;
+                }
+            }
+        }
+
+        panic!("no packet with header {} arrived", header);
+    }
+
+    // Returns (session key, reconnect ticket, restore token)
+    pub fn wait_for_create(&mut self) -> (String, Vec<u8>, Vec<u8>) {
+        let body = self.wait_for(CREATE);
+        let mut buf = &body[..];
+
+        (read_string_u8(&mut buf).unwrap(), read_bytes_u8(&mut buf).unwrap(), read_bytes_u8(&mut buf).unwrap())
+    }
+
+    pub fn wait_for_error(&mut self) -> u16 {
+        let body = self.wait_for(ERROR);
+        let mut buf = &body[..];
+
+        read_u32(&mut buf).unwrap();
+        read_u16(&mut buf).unwrap()
+    }
+
+    pub fn send_raw(&self, data: &[u8]) {
+        self.socket.send_to(data, self.server).unwrap();
+    }
+
+    // The next datagram from the server, None if nothing arrived for a while
+    pub fn recv_raw(&self) -> Option<Vec<u8>> {
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let mut data = [0; 2048];
+
+        while Instant::now() < deadline {
+            if let Ok((len, _)) = self.socket.recv_from(&mut data) {
+                return Some(data[..len].to_vec());
+            }
+        }
+
+        None
+    }
+
+    // Hello without a cookie until the Retry arrives, the server may not have bound its socket yet
+    pub fn request_cookie(&mut self) -> Vec<u8> {
+        let deadline = Instant::now() + TIMEOUT;
+
+        loop {
+            assert!(Instant::now() < deadline, "the server never answered Hello");
+
+            self.next_id = 0;
+            self.send(HELLO, &hello_payload(&[]));
+
+            let mut data = [0; 512];
+
+            // [type 4][header u16][cookie]
+            if let Ok((len, _)) = self.socket.recv_from(&mut data) {
+                if len > 3 && data[0] == 4 {
+                    let mut buf = &data[3..len];
+                    return read_bytes_u8(&mut buf).unwrap();
+                }
+            }
+        }
+    }
+
+    // Hello with the cookie from the retry, the server only answers once the address is proven
+    pub fn hello(&mut self) {
+        let cookie = self.request_cookie();
+
+        self.next_id = 0;
+        self.send(HELLO, &hello_payload(&cookie));
+    }
+
+    // Hello, the cookie retry, then Auth
+    pub fn authenticate(&mut self) {
+        self.authenticate_as(CLIENT_HASH);
+
+        assert_eq!(self.wait_for(AUTH), vec![1], "the server rejected the client hash");
+    }
+
+    // Signs the server's challenge with `secret`, the reply is left to the caller
+    pub fn authenticate_as(&mut self, secret: &str) {
+        self.hello();
+
+        let welcome = self.wait_for(HELLO);
+        let mut buf = &welcome[6..];
+        let nonce = read_bytes_u8(&mut buf).unwrap();
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(&nonce);
+
+        let mut payload = Vec::new();
+        write_bytes_u8(&mut payload, &hmac.finalize().into_bytes());
+        self.send(AUTH, &payload);
+    }
+
+    // Takes over the state of whoever the ticket was issued to, from this client's address
+    pub fn reconnect(&mut self, ticket: &[u8]) {
+        let cookie = self.request_cookie();
+        let mut payload = Vec::new();
+
+        write_bytes_u8(&mut payload, ticket);
+        write_bytes_u8(&mut payload, &cookie);
+
+        self.next_id = 0;
+        self.send(RECONNECT, &payload);
+    }
+
+    fn recv_data(&mut self) -> Option<(u16, Vec<u8>)> {
+        let mut data = [0; 2048];
+        let (len, _) = self.socket.recv_from(&mut data).ok()?;
+        let mut buf = &data[..len];
+
+        // only data packets are reliable and acked
+        if buf.first() != Some(&1) {
+            return None;
+        }
+
+        buf = &buf[1..];
+
+        let id = read_u32(&mut buf)?;
+        let header = read_u16(&mut buf)?;
+
+        let mut ack = Vec::new();
+        write_u32(&mut ack, id);
+        self.send(ACK, &ack);
+
+        Some((header, buf.to_vec()))
+    }
+}
+
+fn hello_payload(cookie: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+
+    write_u16(&mut payload, PROTOCOL_VERSION);
+    write_u32(&mut payload, 0);
+    write_bytes_u8(&mut payload, cookie);
+
+    payload
+}
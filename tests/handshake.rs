@@ -0,0 +1,78 @@
+mod common;
+
+use common::{TestClient, TestServer, HELLO};
+use matchmaker::packets::{read_u16, read_u32, write_u16, write_u32, PROTOCOL_VERSION};
+
+const UNSUPPORTED_VERSION: u16 = 4;
+const HANDSHAKE_REQUIRED: u16 = 5;
+const AUTHENTICATION_FAILED: u16 = 6;
+
+// [id u32][header u16][protocol version u16][capabilities u32], a cookie-less Hello padded to `len`
+fn hello(protocol_version: u16, len: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    write_u32(&mut data, 0);
+    write_u16(&mut data, HELLO);
+    write_u16(&mut data, protocol_version);
+    write_u32(&mut data, 0);
+    data.resize(data.len().max(len), 0);
+
+    data
+}
+
+#[test]
+fn handshake_replies_never_outgrow_the_request() {
+    let dir = common::temp_dir("handshake");
+    let server = TestServer::start(common::test_config(&dir));
+
+    // an outdated but padded Hello is told to update
+    let client = TestClient::unauthenticated(server.address);
+    let request = hello(1, 64);
+    client.send_raw(&request);
+
+    let reply = client.recv_raw().expect("outdated clients are told to update");
+    assert!(reply.len() <= request.len());
+
+    // [type 1][id u32][header u16][request id u32][reason u16]
+    let mut buf = &reply[7..];
+    read_u32(&mut buf).unwrap();
+    assert_eq!(read_u16(&mut buf), Some(UNSUPPORTED_VERSION));
+
+    // the oldest clients never padded, any reply would be larger than what they sent
+    let client = TestClient::unauthenticated(server.address);
+    client.send_raw(&hello(1, 0));
+    assert_eq!(client.recv_raw(), None);
+
+    // a current Hello without a cookie gets a Retry, but only when padded
+    let client = TestClient::unauthenticated(server.address);
+    let request = hello(PROTOCOL_VERSION, 64);
+    client.send_raw(&request);
+
+    let reply = client.recv_raw().expect("a padded Hello gets a cookie");
+    assert_eq!(reply[0], 4);
+    assert!(reply.len() <= request.len());
+
+    let client = TestClient::unauthenticated(server.address);
+    client.send_raw(&hello(PROTOCOL_VERSION, 0));
+    assert_eq!(client.recv_raw(), None);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn only_listed_builds_authenticate() {
+    let dir = common::temp_dir("auth");
+    let server = TestServer::start(common::test_config(&dir));
+
+    let mut client = TestClient::unauthenticated(server.address);
+    client.authenticate_as("NOT A BUILD");
+    assert_eq!(client.wait_for_error(), AUTHENTICATION_FAILED);
+
+    // nothing but the handshake is answered until a build signed the challenge
+    client.create(2);
+    assert_eq!(client.wait_for_error(), HANDSHAKE_REQUIRED);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
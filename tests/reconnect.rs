@@ -0,0 +1,29 @@
+mod common;
+
+use common::{TestClient, TestServer, RECONNECT};
+
+const TICKET_REJECTED: u16 = 7;
+
+#[test]
+fn ticket_moves_the_client_only_once() {
+    let dir = common::temp_dir("reconnect");
+    let server = TestServer::start(common::test_config(&dir));
+
+    let mut host = TestClient::connect(server.address);
+    host.create(2);
+    let (_, ticket, _) = host.wait_for_create();
+
+    // the host's NAT rebound, it comes back from a new port
+    let mut rebound = TestClient::unauthenticated(server.address);
+    rebound.reconnect(&ticket);
+    let new_ticket = rebound.wait_for(RECONNECT);
+    assert_ne!(new_ticket, ticket);
+
+    // whoever captured the old ticket can't use it again
+    let mut replay = TestClient::unauthenticated(server.address);
+    replay.reconnect(&ticket);
+    assert_eq!(replay.wait_for_error(), TICKET_REJECTED);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{TestClient, TestServer};
+
+const BANNED: u16 = 10;
+const BUILD_BLOCKED: u16 = 11;
+
+fn write_builds(path: &std::path::Path, status: &str) {
+    std::fs::write(path, format!("[[build]]\nhash = \"ABCDEF\"\nname = \"test build\"\nstatus = \"{}\"\n", status)).unwrap();
+}
+
+#[test]
+fn reload_drops_clients_whose_build_was_blocked() {
+    let dir = common::temp_dir("reload-builds");
+    let hashes = dir.join("hashes.toml");
+    write_builds(&hashes, "allowed");
+
+    let mut config = common::test_config(&dir);
+    config.files.hashes = hashes.to_string_lossy().into_owned();
+    common::enable_admin(&mut config);
+
+    let server = TestServer::start(config.clone());
+    let mut client = TestClient::connect(server.address);
+
+    write_builds(&hashes, "blocked");
+    common::admin_command(&config, "reload");
+
+    assert_eq!(client.wait_for_error(), BUILD_BLOCKED);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ban_drops_the_connected_client() {
+    let dir = common::temp_dir("reload-bans");
+    let mut config = common::test_config(&dir);
+    common::enable_admin(&mut config);
+
+    let server = TestServer::start(config.clone());
+    let mut client = TestClient::connect(server.address);
+
+    common::admin_command(&config, &format!("ban {}", client.address().ip()));
+    assert_eq!(client.wait_for_error(), BANNED);
+
+    // and stays out
+    let mut banned = TestClient::unauthenticated(server.address);
+    banned.hello();
+    assert_eq!(banned.wait_for_error(), BANNED);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{TestClient, TestServer};
+use matchmaker::config::{Build, BuildList};
+use matchmaker::ServerEvent;
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+fn hosting_and_joining_emit_session_created_and_match_made() {
+    let dir = common::temp_dir("events");
+    let (tx, rx) = mpsc::channel();
+
+    let server = TestServer::start_with(common::test_config(&dir), move |builder| {
+        builder.on_event(move |event| {
+            // the test may have finished already
+            let _ = tx.send(event.clone());
+        })
+    });
+
+    let mut host = TestClient::connect(server.address);
+    host.create(2);
+    let (session_key, _, _) = host.wait_for_create();
+
+    match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+        ServerEvent::SessionCreated { host: host_addr, session_key: created_key } => {
+            assert_eq!(host_addr, host.address());
+            assert_eq!(created_key, session_key);
+        },
+        event => panic!("expected SessionCreated, got {:?}", event)
+    }
+
+    let mut joiner = TestClient::connect(server.address);
+    joiner.join(&session_key);
+    joiner.wait_for(common::START);
+
+    match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+        ServerEvent::MatchMade { members } => assert_eq!(members, vec![host.address(), joiner.address()]),
+        event => panic!("expected MatchMade, got {:?}", event)
+    }
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn shutdown_reports_every_client_dropped() {
+    let dir = common::temp_dir("events-shutdown");
+    let (tx, rx) = mpsc::channel();
+
+    let server = TestServer::start_with(common::test_config(&dir), move |builder| {
+        // the same secret as hashes.toml, handed in without the file
+        let builds = BuildList::new(vec![Build::new("ABCDEF", "test build", "default")]);
+
+        builder.builds(builds).on_event(move |event| {
+            let _ = tx.send(event.clone());
+        })
+    });
+
+    let client = TestClient::connect(server.address);
+    server.stop();
+
+    match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+        ServerEvent::ClientDropped { client: dropped } => assert_eq!(dropped, client.address()),
+        event => panic!("expected ClientDropped, got {:?}", event)
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
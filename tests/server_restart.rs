@@ -0,0 +1,34 @@
+mod common;
+
+use common::{TestClient, TestServer};
+use std::net::{Ipv4Addr, TcpListener, UdpSocket};
+
+#[test]
+fn stopped_server_releases_every_port() {
+    let dir = common::temp_dir("ports");
+    let mut config = common::test_config(&dir);
+    config.network.echo_port = Some(common::free_port());
+    config.metrics.enabled = true;
+    config.metrics.port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port();
+    config.admin.enabled = true;
+    config.admin.port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port();
+
+    let server = TestServer::start(config.clone());
+    let mut client = TestClient::connect(server.address);
+    server.stop();
+
+    // late datagrams must not bring down a thread of the stopped server
+    client.create(2);
+
+    UdpSocket::bind((Ipv4Addr::LOCALHOST, config.network.port)).unwrap();
+    UdpSocket::bind((Ipv4Addr::LOCALHOST, config.network.echo_port.unwrap())).unwrap();
+    TcpListener::bind((Ipv4Addr::LOCALHOST, config.metrics.port)).unwrap();
+    TcpListener::bind((Ipv4Addr::LOCALHOST, config.admin.port)).unwrap();
+
+    // and a new server can take over the same ports in the same process
+    let server = TestServer::start(config);
+    TestClient::connect(server.address);
+    server.stop();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{TestClient, TestServer, JOIN};
+
+const INVALID_PASSWORD: u16 = 1;
+
+#[test]
+fn password_is_asked_for_once_the_session_saw_failed_joins() {
+    let dir = common::temp_dir("password");
+    let server = TestServer::start(common::test_config(&dir));
+
+    let mut host = TestClient::connect(server.address);
+    host.create_with_password(4, "secret", 1);
+    let (session_key, _, _) = host.wait_for_create();
+
+    // friends with the key get straight in until someone guesses
+    let mut friend = TestClient::connect(server.address);
+    friend.join(&session_key);
+    friend.wait_for(JOIN);
+
+    // a wrong key anywhere could have been a guess at this session
+    let mut guesser = TestClient::connect(server.address);
+    guesser.join("ZZZZZ");
+    guesser.wait_for(JOIN);
+
+    let mut stranger = TestClient::connect(server.address);
+    stranger.join(&session_key);
+    assert_eq!(stranger.wait_for_error(), INVALID_PASSWORD);
+
+    let mut invited = TestClient::connect(server.address);
+    invited.join_with_password(&session_key, "secret");
+    invited.wait_for(JOIN);
+
+    // the successful join started the count over
+    let mut late_friend = TestClient::connect(server.address);
+    late_friend.join(&session_key);
+    late_friend.wait_for(JOIN);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{TestClient, TestServer};
+use matchmaker::ServerEvent;
+use std::sync::mpsc;
+
+const RESTORE_REJECTED: u16 = 16;
+
+#[test]
+fn host_gets_its_session_back_after_a_restart() {
+    let dir = common::temp_dir("restore");
+    let mut config = common::test_config(&dir);
+    config.snapshot.path = dir.join("snapshot.json").to_string_lossy().into_owned();
+
+    let server = TestServer::start(config.clone());
+    let mut host = TestClient::connect(server.address);
+    host.create(2);
+    let (session_key, _, token) = host.wait_for_create();
+    server.stop();
+
+    // same port, the stopped server released it, but the host comes back from a new socket
+    let (tx, rx) = mpsc::channel();
+    let server = TestServer::start_with(config, move |builder| {
+        builder.on_event(move |event| {
+            let _ = tx.send(event.clone());
+        })
+    });
+
+    let mut intruder = TestClient::connect(server.address);
+    intruder.restore(&session_key, &[0; 16]);
+    assert_eq!(intruder.wait_for_error(), RESTORE_REJECTED);
+
+    let mut host = TestClient::connect(server.address);
+    host.restore(&session_key, &token);
+    let (restored_key, _, new_token) = host.wait_for_create();
+
+    assert_eq!(restored_key, session_key);
+    assert_ne!(new_token, token);
+
+    // embedders see the session come back like any other
+    let created = rx.try_iter().find_map(|event| match event {
+        ServerEvent::SessionCreated { host: host_addr, session_key } => Some((host_addr, session_key)),
+        _ => None
+    });
+    assert_eq!(created, Some((host.address(), session_key.clone())));
+
+    // the session is taken, the token can't be used a second time
+    let mut intruder = TestClient::connect(server.address);
+    intruder.restore(&session_key, &token);
+    assert_eq!(intruder.wait_for_error(), RESTORE_REJECTED);
+
+    server.stop();
+    let _ = std::fs::remove_dir_all(&dir);
+}